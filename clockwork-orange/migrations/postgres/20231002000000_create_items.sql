@@ -0,0 +1,11 @@
+CREATE TABLE items (
+    list_id BIGINT NOT NULL,
+    key TEXT NOT NULL,
+    author TEXT NOT NULL,
+    content TEXT NOT NULL,
+    read_at TIMESTAMPTZ,
+    PRIMARY KEY (list_id, key)
+);
+
+CREATE INDEX items_unread_idx ON items (list_id) WHERE read_at IS NULL;
+CREATE INDEX items_author_idx ON items (list_id, author) WHERE read_at IS NULL;
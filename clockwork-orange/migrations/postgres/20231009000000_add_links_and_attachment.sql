@@ -0,0 +1,4 @@
+-- space-separated, see `ContentItem::links`
+ALTER TABLE items ADD COLUMN links TEXT NOT NULL DEFAULT '';
+ALTER TABLE items ADD COLUMN attachment_kind TEXT;
+ALTER TABLE items ADD COLUMN attachment_file_id TEXT;
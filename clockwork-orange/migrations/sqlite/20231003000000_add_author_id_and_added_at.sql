@@ -0,0 +1,4 @@
+ALTER TABLE items ADD COLUMN author_id BIGINT;
+ALTER TABLE items ADD COLUMN added_at TEXT;
+
+CREATE INDEX items_author_id_idx ON items (list_id, author_id) WHERE read_at IS NULL;
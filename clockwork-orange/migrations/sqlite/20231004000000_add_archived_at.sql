@@ -0,0 +1,3 @@
+ALTER TABLE items ADD COLUMN archived_at TEXT;
+
+CREATE INDEX items_archived_idx ON items (list_id) WHERE archived_at IS NOT NULL;
@@ -0,0 +1 @@
+ALTER TABLE items ADD COLUMN shown_at TEXT;
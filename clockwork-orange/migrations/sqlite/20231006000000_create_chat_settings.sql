@@ -0,0 +1,4 @@
+CREATE TABLE chat_settings (
+    list_id INTEGER NOT NULL PRIMARY KEY,
+    settings TEXT NOT NULL
+);
@@ -0,0 +1 @@
+ALTER TABLE items ADD COLUMN snoozed_until TEXT;
@@ -0,0 +1,3 @@
+ALTER TABLE items ADD COLUMN category TEXT;
+-- space-separated, see `ContentItem::tags`
+ALTER TABLE items ADD COLUMN tags TEXT NOT NULL DEFAULT '';
@@ -0,0 +1,5 @@
+ALTER TABLE items ADD COLUMN source_chat_id BIGINT;
+ALTER TABLE items ADD COLUMN source_message_id INTEGER;
+ALTER TABLE items ADD COLUMN forward_author TEXT;
+ALTER TABLE items ADD COLUMN forward_chat_id BIGINT;
+ALTER TABLE items ADD COLUMN forward_message_id INTEGER;
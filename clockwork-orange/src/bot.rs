@@ -4,15 +4,16 @@ use color_eyre::{eyre::WrapErr, Report, Result};
 use teloxide::{
     adaptors::DefaultParseMode,
     dispatching::{DefaultKey, HandlerExt, UpdateFilterExt},
-    payloads::SendMessageSetters,
+    payloads::{EditMessageTextSetters, SendMessageSetters},
     prelude::Dispatcher as TgDispatcher,
     requests::{Requester, RequesterExt},
-    types::{ChatId, InlineKeyboardMarkup, ParseMode, Update},
+    types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, ParseMode, Update},
     utils::command::BotCommands,
-    Bot as TgBot,
+    ApiError, Bot as TgBot, RequestError,
 };
 
 use crate::{
+    classifier::Classifier,
     config::Config,
     content_item::ContentItem,
     storage::{Key, Storage, StorageBackend},
@@ -24,10 +25,25 @@ use callbacks::Callback;
 mod commands;
 use commands::Command;
 
+mod duplicates;
+
+mod entry;
+
 mod extractors;
 
 mod handlers;
+
+mod history;
+
+mod list;
+
+mod pages;
+
+mod random;
+
+mod snooze;
 use handlers::{add_new_entry, handle_command};
+use random::RandomSessions;
 
 pub type Bot = DefaultParseMode<TgBot>;
 pub type Dispatcher<'a> = TgDispatcher<Bot, Report, DefaultKey>;
@@ -44,8 +60,10 @@ pub async fn create_bot() -> Result<Bot> {
 pub async fn create_bot_and_dispatcher<B: StorageBackend + Debug + 'static>(
     storage: Storage<B>,
     config: &Config,
-) -> Result<(Bot, Dispatcher)> {
+) -> Result<(Bot, Dispatcher<'_>)> {
     let bot = create_bot().await.wrap_err("Failed to create bot")?;
+    let classifier = Classifier::load(config.classifier_rules_path.as_deref())
+        .wrap_err("Failed to load classifier rules")?;
 
     let handler = dptree::entry()
         // generic Command handler
@@ -66,7 +84,7 @@ pub async fn create_bot_and_dispatcher<B: StorageBackend + Debug + 'static>(
         // any other text message – append to diary
         .branch(
             Update::filter_message()
-                .filter_map(extractors::get_message_text)
+                .filter_map(extractors::get_message_content)
                 .filter_map(extractors::get_message_author)
                 .endpoint(add_new_entry::<B>),
         );
@@ -74,13 +92,19 @@ pub async fn create_bot_and_dispatcher<B: StorageBackend + Debug + 'static>(
     Ok((
         bot.clone(),
         TgDispatcher::builder(bot, handler)
-            .dependencies(dptree::deps![storage, config.clone()])
+            .dependencies(dptree::deps![
+                storage,
+                config.clone(),
+                classifier,
+                RandomSessions::default()
+            ])
             .enable_ctrlc_handler()
             .build(),
     ))
 }
 
-/// Send a message to chat, with a button to mark the item as watched
+/// Send a message to chat, with buttons to mark the item as watched or delete it,
+/// or with a button to restore it for archived items
 #[tracing::instrument(skip(requester, item))]
 pub(self) async fn send_item_to_chat<R>(
     requester: R,
@@ -96,11 +120,90 @@ where
 
     requester
         .send_message(chat_id, &message_text)
-        .reply_markup(InlineKeyboardMarkup::new(vec![vec![
-            Callback::mark_as_read(key).as_button("☑️ Mark as watched"),
-        ]]))
+        .reply_markup(item_keyboard(item, key))
         .await
         .wrap_err_with(|| format!("Failed to send a message to chat, message: {message_text}"))?;
 
     Ok(())
 }
+
+/// Edit the item message to show the current state of the item, e.g. after it was marked as read
+async fn edit_item_message(
+    bot: &Bot,
+    item: &ContentItem,
+    key: &Key,
+    chat_id: ChatId,
+    message_id: MessageId,
+) -> Result<()> {
+    edit_message(
+        bot,
+        chat_id,
+        message_id,
+        item.to_tg_message_text(),
+        item_keyboard(item, key),
+    )
+    .await
+}
+
+/// Replace text and buttons of the message.
+///
+/// Edits which don't change anything are ignored, so double taps on a button are harmless.
+#[tracing::instrument(skip(bot, text, keyboard))]
+async fn edit_message(
+    bot: &Bot,
+    chat_id: ChatId,
+    message_id: MessageId,
+    text: String,
+    keyboard: InlineKeyboardMarkup,
+) -> Result<()> {
+    let result = bot
+        .edit_message_text(chat_id, message_id, text)
+        .reply_markup(keyboard)
+        .await;
+
+    match result {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+        Err(err) => Err(err).wrap_err("Failed to edit message"),
+    }
+}
+
+/// Buttons attached to the item message shown by `/random`
+fn random_keyboard(item: &ContentItem, key: &Key) -> InlineKeyboardMarkup {
+    let mut keyboard = vec![vec![
+        Callback::random_another(key).as_button("🎲 Another"),
+        Callback::random_snooze(key).as_button("⏰ Snooze"),
+        Callback::random_watched(key).as_button("☑️ Watched"),
+    ]];
+    keyboard.extend(original_button(item, key));
+
+    InlineKeyboardMarkup::new(keyboard)
+}
+
+/// Row with the button to send the message the item was added from, if it's known
+fn original_button(item: &ContentItem, key: &Key) -> Option<Vec<InlineKeyboardButton>> {
+    item.source()
+        .map(|_| vec![Callback::original(key).as_button("↪️ Original")])
+}
+
+/// Buttons attached to the item message
+fn item_keyboard(item: &ContentItem, key: &Key) -> InlineKeyboardMarkup {
+    if item.is_archived() {
+        return InlineKeyboardMarkup::new(vec![vec![
+            Callback::restore(key).as_button("♻️ Restore")
+        ]]);
+    }
+    if item.is_read() {
+        return InlineKeyboardMarkup::new(vec![vec![
+            Callback::mark_as_unread(key).as_button("↩️ Undo")
+        ]]);
+    }
+
+    let mut keyboard = vec![vec![
+        Callback::mark_as_read(key).as_button("☑️ Mark as watched"),
+        Callback::snooze_menu(key).as_button("⏰ Snooze"),
+        Callback::delete(key).as_button("🗑 Delete"),
+    ]];
+    keyboard.extend(original_button(item, key));
+
+    InlineKeyboardMarkup::new(keyboard)
+}
@@ -3,12 +3,70 @@ use tracing::debug;
 
 use crate::storage::Key;
 
+use super::{history::HistoryQuery, list::ListView};
+
 /// Callbacks are used to handle user interaction with bot.
 ///
-/// Currently the only callback type is `mark-as-read`, more may be added in the future.
+/// Payload is `<kind>:<item key>`, callbacks without an item have an empty key part.
+/// `history` callbacks carry the page and the query instead: `history:<page>:<query>`,
+/// list callbacks carry the page and the view: `list:<page>:<view>`
+/// and `list-read:<page>:<view>:<item key>`, or just the page in the archive:
+/// `list-restore:<page>:<item key>`, `snooze` carries the days: `snooze:<days>:<item key>`.
 #[derive(Debug, Clone)]
 pub enum Callback {
     MarkAsRead(Key),
+    /// Return the item to the queue, undoing `MarkAsRead`
+    MarkAsUnread(Key),
+    /// Ask for confirmation before moving the item to the archive
+    Delete(Key),
+    ConfirmDelete(Key),
+    CancelDelete,
+    /// Return the item from the archive
+    Restore(Key),
+    /// Show another page of the history
+    History {
+        page: usize,
+        query: HistoryQuery,
+    },
+    /// Send the item as a separate message, with its own buttons
+    Show(Key),
+    /// Show another item in the `/random` message instead of this one
+    RandomAnother(Key),
+    /// Show another item in the `/random` message and snooze this one for `RANDOM_SNOOZE_DAYS`
+    RandomSnooze(Key),
+    /// Mark the item shown in the `/random` message as read
+    RandomWatched(Key),
+    /// Show another page of the list
+    List {
+        view: ListView,
+        page: usize,
+    },
+    /// Mark the item as read from the list and re-render the page
+    ListMarkAsRead {
+        view: ListView,
+        page: usize,
+        key: Key,
+    },
+    /// Restore the item from the `/archive` list and re-render the page
+    ListRestore {
+        page: usize,
+        key: Key,
+    },
+    /// Show buttons to choose how long to snooze the item for
+    SnoozeMenu(Key),
+    /// Snooze the item for the number of days
+    Snooze {
+        key: Key,
+        days: u16,
+    },
+    /// Cancel the snooze of the item
+    Unsnooze(Key),
+    /// Close the snooze menu, returning the item buttons
+    SnoozeCancel(Key),
+    /// Save the message the duplicate warning replies to, despite the duplicate
+    AddAnyway,
+    /// Send the message the item was added from – forwarded or copied, media included
+    Original(Key),
 }
 
 impl Callback {
@@ -16,13 +74,86 @@ impl Callback {
     fn kind_as_str(&self) -> &str {
         match self {
             Self::MarkAsRead(_) => "mark-as-read",
+            Self::MarkAsUnread(_) => "mark-as-unread",
+            Self::Delete(_) => "delete",
+            Self::ConfirmDelete(_) => "confirm-delete",
+            Self::CancelDelete => "cancel-delete",
+            Self::Restore(_) => "restore",
+            Self::History { .. } => "history",
+            Self::Show(_) => "show",
+            Self::RandomAnother(_) => "random-another",
+            Self::RandomSnooze(_) => "random-snooze",
+            Self::RandomWatched(_) => "random-watched",
+            Self::List { .. } => "list",
+            Self::ListMarkAsRead { .. } => "list-read",
+            Self::ListRestore { .. } => "list-restore",
+            Self::SnoozeMenu(_) => "snooze-menu",
+            Self::Snooze { .. } => "snooze",
+            Self::Unsnooze(_) => "unsnooze",
+            Self::SnoozeCancel(_) => "snooze-cancel",
+            Self::AddAnyway => "add-anyway",
+            Self::Original(_) => "original",
+        }
+    }
+
+    /// Item the callback is about, if it's attached to a message about a single item.
+    ///
+    /// Buttons of lists are about one of several items, so they give `None`.
+    pub fn key(&self) -> Option<&Key> {
+        match self {
+            Self::MarkAsRead(key)
+            | Self::MarkAsUnread(key)
+            | Self::Delete(key)
+            | Self::ConfirmDelete(key)
+            | Self::Restore(key)
+            | Self::RandomAnother(key)
+            | Self::RandomSnooze(key)
+            | Self::RandomWatched(key)
+            | Self::SnoozeMenu(key)
+            | Self::Snooze { key, .. }
+            | Self::Unsnooze(key)
+            | Self::SnoozeCancel(key)
+            | Self::Original(key) => Some(key),
+            Self::Show(_) | Self::ListMarkAsRead { .. } | Self::ListRestore { .. } => None,
+            Self::CancelDelete | Self::History { .. } | Self::List { .. } | Self::AddAnyway => None,
         }
     }
 
     /// Transform callback to payload for sending to TG API
     pub fn to_payload(&self) -> String {
         let res = match self {
-            Self::MarkAsRead(key) => format!("{}:{}", self.kind_as_str(), key.as_ref()),
+            Self::MarkAsRead(key)
+            | Self::MarkAsUnread(key)
+            | Self::Delete(key)
+            | Self::ConfirmDelete(key)
+            | Self::Restore(key)
+            | Self::Show(key)
+            | Self::RandomAnother(key)
+            | Self::RandomSnooze(key)
+            | Self::RandomWatched(key)
+            | Self::SnoozeMenu(key)
+            | Self::Unsnooze(key)
+            | Self::SnoozeCancel(key)
+            | Self::Original(key) => format!("{}:{}", self.kind_as_str(), key.as_ref()),
+            Self::CancelDelete | Self::AddAnyway => format!("{}:", self.kind_as_str()),
+            Self::History { page, query } => {
+                format!("{}:{page}:{}", self.kind_as_str(), query.to_payload())
+            }
+            Self::List { view, page } => {
+                format!("{}:{page}:{}", self.kind_as_str(), view.to_payload())
+            }
+            Self::ListMarkAsRead { view, page, key } => format!(
+                "{}:{page}:{}:{}",
+                self.kind_as_str(),
+                view.to_payload(),
+                key.as_ref()
+            ),
+            Self::ListRestore { page, key } => {
+                format!("{}:{page}:{}", self.kind_as_str(), key.as_ref())
+            }
+            Self::Snooze { key, days } => {
+                format!("{}:{days}:{}", self.kind_as_str(), key.as_ref())
+            }
         };
         debug_assert!(
             res.len() <= 64,
@@ -38,6 +169,107 @@ impl Callback {
         Self::MarkAsRead(key.clone())
     }
 
+    /// Create callback item with `mark-as-unread` kind
+    pub fn mark_as_unread(key: &Key) -> Self {
+        Self::MarkAsUnread(key.clone())
+    }
+
+    /// Create callback item with `delete` kind
+    pub fn delete(key: &Key) -> Self {
+        Self::Delete(key.clone())
+    }
+
+    /// Create callback item with `confirm-delete` kind
+    pub fn confirm_delete(key: &Key) -> Self {
+        Self::ConfirmDelete(key.clone())
+    }
+
+    /// Create callback item with `restore` kind
+    pub fn restore(key: &Key) -> Self {
+        Self::Restore(key.clone())
+    }
+
+    /// Create callback item with `history` kind
+    pub fn history(page: usize, query: &HistoryQuery) -> Self {
+        Self::History {
+            page,
+            query: *query,
+        }
+    }
+
+    /// Create callback item with `show` kind
+    pub fn show(key: &Key) -> Self {
+        Self::Show(key.clone())
+    }
+
+    /// Create callback item with `random-another` kind
+    pub fn random_another(key: &Key) -> Self {
+        Self::RandomAnother(key.clone())
+    }
+
+    /// Create callback item with `random-snooze` kind
+    pub fn random_snooze(key: &Key) -> Self {
+        Self::RandomSnooze(key.clone())
+    }
+
+    /// Create callback item with `random-watched` kind
+    pub fn random_watched(key: &Key) -> Self {
+        Self::RandomWatched(key.clone())
+    }
+
+    /// Create callback item with `list` kind
+    pub fn list(view: &ListView, page: usize) -> Self {
+        Self::List {
+            view: view.clone(),
+            page,
+        }
+    }
+
+    /// Create callback item with `list-read` kind
+    pub fn list_mark_as_read(view: &ListView, page: usize, key: &Key) -> Self {
+        Self::ListMarkAsRead {
+            view: view.clone(),
+            page,
+            key: key.clone(),
+        }
+    }
+
+    /// Create callback item with `list-restore` kind
+    pub fn list_restore(page: usize, key: &Key) -> Self {
+        Self::ListRestore {
+            page,
+            key: key.clone(),
+        }
+    }
+
+    /// Create callback item with `snooze-menu` kind
+    pub fn snooze_menu(key: &Key) -> Self {
+        Self::SnoozeMenu(key.clone())
+    }
+
+    /// Create callback item with `snooze` kind
+    pub fn snooze(key: &Key, days: u16) -> Self {
+        Self::Snooze {
+            key: key.clone(),
+            days,
+        }
+    }
+
+    /// Create callback item with `unsnooze` kind
+    pub fn unsnooze(key: &Key) -> Self {
+        Self::Unsnooze(key.clone())
+    }
+
+    /// Create callback item with `snooze-cancel` kind
+    pub fn snooze_cancel(key: &Key) -> Self {
+        Self::SnoozeCancel(key.clone())
+    }
+
+    /// Create callback item with `original` kind
+    pub fn original(key: &Key) -> Self {
+        Self::Original(key.clone())
+    }
+
     /// Create button for sending to TG API
     pub fn as_button(&self, text: impl Into<String>) -> InlineKeyboardButton {
         let payload = self.to_payload();
@@ -49,13 +281,108 @@ impl Callback {
     pub fn from_payload(payload: &str) -> Option<Self> {
         debug!("got callback with payload: {payload}");
         let (kind, data) = payload.split_once(':')?;
+        let key = || Key::from(data.to_string());
 
         match kind {
-            "mark-as-read" => {
-                let key = Key::from(data.to_string());
-                Some(Self::MarkAsRead(key))
+            "mark-as-read" => Some(Self::MarkAsRead(key())),
+            "mark-as-unread" => Some(Self::MarkAsUnread(key())),
+            "delete" => Some(Self::Delete(key())),
+            "confirm-delete" => Some(Self::ConfirmDelete(key())),
+            "cancel-delete" => Some(Self::CancelDelete),
+            "restore" => Some(Self::Restore(key())),
+            "history" => {
+                let (page, query) = data.split_once(':')?;
+
+                Some(Self::History {
+                    page: page.parse().ok()?,
+                    query: HistoryQuery::from_payload(query)?,
+                })
+            }
+            "show" => Some(Self::Show(key())),
+            "random-another" => Some(Self::RandomAnother(key())),
+            "random-snooze" => Some(Self::RandomSnooze(key())),
+            "random-watched" => Some(Self::RandomWatched(key())),
+            "list" => {
+                let (page, view) = data.split_once(':')?;
+
+                Some(Self::List {
+                    view: ListView::from_payload(view)?,
+                    page: page.parse().ok()?,
+                })
             }
+            "list-read" => {
+                let mut parts = data.splitn(3, ':');
+                let (page, view, key) = (parts.next()?, parts.next()?, parts.next()?);
+
+                Some(Self::ListMarkAsRead {
+                    view: ListView::from_payload(view)?,
+                    page: page.parse().ok()?,
+                    key: Key::from(key.to_string()),
+                })
+            }
+            "list-restore" => {
+                let (page, key) = data.split_once(':')?;
+
+                Some(Self::ListRestore {
+                    page: page.parse().ok()?,
+                    key: Key::from(key.to_string()),
+                })
+            }
+            "snooze-menu" => Some(Self::SnoozeMenu(key())),
+            "snooze" => {
+                let (days, key) = data.split_once(':')?;
+
+                Some(Self::Snooze {
+                    key: Key::from(key.to_string()),
+                    days: days.parse().ok()?,
+                })
+            }
+            "unsnooze" => Some(Self::Unsnooze(key())),
+            "snooze-cancel" => Some(Self::SnoozeCancel(key())),
+            "add-anyway" => Some(Self::AddAnyway),
+            "original" => Some(Self::Original(key())),
             _ => None,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::UserId;
+    use time::Month;
+
+    use super::*;
+    use crate::bot::{history::Period, list::MAX_TAG_LEN, pages::MAX_PAGES};
+
+    #[test]
+    /// Test that the longest payloads fit into 64 bytes Telegram allows
+    fn test_longest_payloads() {
+        let key = Key::generate();
+        let page = MAX_PAGES - 1;
+        let views = [
+            ListView::Unread(Some("x".repeat(MAX_TAG_LEN))),
+            ListView::AllMy(UserId(u64::MAX)),
+            ListView::Archive,
+        ];
+        let history = HistoryQuery {
+            period: Period::Month(-9999, Month::December),
+            author: Some(UserId(u64::MAX)),
+        };
+
+        let mut callbacks = vec![
+            Callback::history(page, &history),
+            Callback::list_restore(page, &key),
+            Callback::snooze(&key, u16::MAX),
+            Callback::original(&key),
+        ];
+        for view in &views {
+            callbacks.push(Callback::list(view, page));
+            callbacks.push(Callback::list_mark_as_read(view, page, &key));
+        }
+
+        for callback in callbacks {
+            let payload = callback.to_payload();
+            assert!(payload.len() <= 64, "{payload} is too long");
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use teloxide::utils::command::BotCommands;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, BotCommands)]
+#[derive(Debug, PartialEq, Eq, Clone, BotCommands)]
 #[command(rename_rule = "snake_case", description = "Available commands")]
 /// Available commands
 pub enum Command {
@@ -10,10 +10,32 @@ pub enum Command {
     /// Get all items created by current user
     #[command(description = "Get all items created by current user")]
     AllMy,
-    /// Get random item from collection
-    #[command(description = "Get random item from collection")]
-    Random,
-    /// Get all unread items
-    #[command(description = "Get all unread items")]
-    Unread,
+    /// Get random item from collection, with buttons to pick another one,
+    /// optionally filtered and weighted, see `bot::random`
+    #[command(
+        description = "Get random item, tap 🎲 to pick another one: /random, /random older #short"
+    )]
+    Random(String),
+    /// Show or change defaults of `/random`
+    #[command(
+        description = "Show or change /random defaults: /random_settings older no-repeat=7d"
+    )]
+    RandomSettings(String),
+    /// Snooze the item, the command is sent in reply to the item message
+    #[command(
+        description = "Snooze item: reply to it with /snooze 10d, /snooze 2w or /snooze 2023-12-31"
+    )]
+    Snooze(String),
+    /// Get all unread items, optionally only ones with the tag or category
+    #[command(description = "Get all unread items: /unread or /unread #horror")]
+    Unread(String),
+    /// Get items deleted to the archive
+    #[command(description = "Get deleted items, to restore them")]
+    Archive,
+    /// Export the whole list as a document, format is JSON by default
+    #[command(description = "Export the whole list as a file: /export or /export csv")]
+    Export(String),
+    /// Show watched items page by page, optionally for a period or only own ones
+    #[command(description = "Show watched items: /history, /history month, /history 2023-10 me")]
+    History(String),
 }
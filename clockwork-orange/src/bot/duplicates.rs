@@ -0,0 +1,137 @@
+//! Duplicates of new items – links are compared in their canonical form,
+//! so the same film shared from IMDb on the phone and on the laptop is found.
+//!
+//! The bot replies to the new message with the existing item instead of saving it,
+//! "Add anyway" saves the message it replied to.
+
+use std::collections::HashMap;
+
+use clockwork_orange_messages::tg_escape;
+use teloxide::types::InlineKeyboardMarkup;
+use url::Url;
+
+use crate::{classifier::Classifier, content_item::ContentItem, storage::Key};
+
+use super::callbacks::Callback;
+
+/// Links of the item in the form to compare them by, from the content
+/// and the ones hidden behind the text
+pub fn links(classifier: &Classifier, item: &ContentItem) -> Vec<Url> {
+    let mut links = classifier.canonical_links(item.content());
+    links.extend(
+        item.links()
+            .iter()
+            .map(|link| classifier.canonical_link(link.clone())),
+    );
+
+    links
+}
+
+/// Find an item with any of the links, unread or watched, but not archived.
+///
+/// Unread items go first, then the ones watched most recently.
+pub fn find<'a>(
+    items: &'a HashMap<Key, ContentItem>,
+    links: &[Url],
+    classifier: &Classifier,
+) -> Option<(&'a Key, &'a ContentItem)> {
+    items
+        .iter()
+        .filter(|(_, item)| !item.is_archived())
+        .filter(|(_, item)| {
+            self::links(classifier, item)
+                .iter()
+                .any(|link| links.contains(link))
+        })
+        .max_by_key(|(key, item)| (!item.is_read(), item.read_at(), *key))
+}
+
+/// Text of the reply about the duplicate, with the existing item
+pub fn message_text(item: &ContentItem) -> String {
+    let header = if item.is_read() {
+        "You've already watched it:"
+    } else {
+        "It's already in the queue:"
+    };
+
+    format!("{}\n\n{}", tg_escape(header), item.to_tg_message_text())
+}
+
+/// Buttons to save the new item anyway, or to return the watched one to the queue
+pub fn keyboard(item: &ContentItem, key: &Key) -> InlineKeyboardMarkup {
+    let mut buttons = vec![Callback::AddAnyway.as_button("➕ Add anyway")];
+    if item.is_read() {
+        buttons.push(Callback::mark_as_unread(key).as_button("↩️ Mark as unwatched again"));
+    }
+
+    InlineKeyboardMarkup::new(vec![buttons])
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{Duration, OffsetDateTime};
+
+    use super::*;
+
+    #[test]
+    /// Test that duplicates are found by canonical links, preferring unread items
+    fn test_find() {
+        let classifier = Classifier::load(None).unwrap();
+        let now = OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap();
+        let item = |content: &str| ContentItem::legacy("alice", content);
+
+        let (watched_key, unread_key, old_key, archived_key, other_key) = (
+            Key::generate(),
+            Key::generate(),
+            Key::generate(),
+            Key::generate(),
+            Key::generate(),
+        );
+        let mut watched = item("https://www.imdb.com/title/tt0066921/");
+        watched.set_read(now);
+        let mut old = item("https://m.imdb.com/title/tt0066921/?ref_=share");
+        old.set_read(now - Duration::days(30));
+        let mut archived = item("Never gonna give you up");
+        archived.set_links([Url::parse("https://youtu.be/dQw4w9WgXcQ").unwrap()]);
+        archived.archive(now);
+
+        let mut items = HashMap::from([
+            (watched_key.clone(), watched),
+            (old_key, old),
+            (archived_key, archived),
+            (other_key, item("https://example.com/?q=dune")),
+        ]);
+        let links = classifier.canonical_links("Let's watch https://imdb.com/title/tt0066921");
+
+        assert_eq!(
+            find(&items, &links, &classifier).map(|(key, _)| key),
+            Some(&watched_key)
+        );
+
+        let unread = item("A Clockwork Orange, reviews: https://imdb.com/title/tt0066921/reviews");
+        items.insert(unread_key.clone(), unread);
+        assert_eq!(
+            find(&items, &links, &classifier).map(|(key, _)| key),
+            Some(&unread_key)
+        );
+
+        let links = classifier.canonical_links("https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+        assert_eq!(find(&items, &links, &classifier), None);
+        items.values_mut().for_each(ContentItem::restore);
+        assert!(find(&items, &links, &classifier).is_some());
+        let links = classifier.canonical_links("https://example.com/?q=dune&utm_source=tg");
+        assert!(find(&items, &links, &classifier).is_some());
+    }
+
+    #[test]
+    /// Test that only watched items can be returned to the queue
+    fn test_keyboard() {
+        let key = Key::generate();
+        let mut item = ContentItem::legacy("alice", "https://example.com");
+        let count = |item: &ContentItem| keyboard(item, &key).inline_keyboard[0].len();
+
+        assert_eq!(count(&item), 1);
+        item.set_read(OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap());
+        assert_eq!(count(&item), 2);
+    }
+}
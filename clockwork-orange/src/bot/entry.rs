@@ -0,0 +1,183 @@
+//! Parsing of new entries – the text of the message becomes the content,
+//! `#hashtags` become tags, links are collected, including the ones hidden behind
+//! the text, and an explicit category can be given as a prefix: `movie: https://...`
+//! or `book: Dune`.
+
+use teloxide::types::{MediaText, MessageEntityKind, MessageEntityRef};
+use url::Url;
+
+use crate::content_item::{normalize_tag, Category};
+
+/// New entry parsed from the message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub content: String,
+    pub category: Option<Category>,
+    pub tags: Vec<String>,
+    pub links: Vec<Url>,
+}
+
+impl Entry {
+    /// Parse the message text, cutting hashtags and the category prefix out of the content.
+    ///
+    /// Messages with nothing but hashtags are kept as they are.
+    pub fn parse(text: &MediaText) -> Self {
+        let entities = MessageEntityRef::parse(&text.text, &text.entities);
+        let hashtags: Vec<_> = entities
+            .iter()
+            .filter(|entity| matches!(entity.kind(), MessageEntityKind::Hashtag))
+            .collect();
+        let links = entities
+            .iter()
+            .filter_map(|entity| match entity.kind() {
+                // Telegram recognizes links without a scheme too, like `example.com`
+                MessageEntityKind::Url => Url::parse(entity.text())
+                    .or_else(|_| Url::parse(&format!("https://{}", entity.text())))
+                    .ok(),
+                MessageEntityKind::TextLink { url } => Some(url.clone()),
+                _ => None,
+            })
+            .collect();
+        let tags = hashtags
+            .iter()
+            .map(|entity| normalize_tag(entity.text()))
+            .collect();
+
+        let content = if hashtags.is_empty() {
+            text.text.clone()
+        } else {
+            let mut content = String::with_capacity(text.text.len());
+            let mut last = 0;
+            for entity in &hashtags {
+                content.push_str(&text.text[last..entity.start()]);
+                last = entity.end();
+            }
+            content.push_str(&text.text[last..]);
+
+            tidy(&content)
+        };
+
+        let (category, content) = match content.split_once(':') {
+            Some((prefix, rest)) => match prefix.parse::<Category>() {
+                Ok(category) => (Some(category), rest.trim().to_string()),
+                Err(_) => (None, content),
+            },
+            None => (None, content),
+        };
+
+        Self {
+            content: if content.is_empty() {
+                text.text.clone()
+            } else {
+                content
+            },
+            category,
+            tags,
+            links,
+        }
+    }
+}
+
+/// Drop whitespace left after cutting hashtags out – at the ends of lines and the text
+fn tidy(text: &str) -> String {
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::MessageEntity;
+
+    use super::*;
+
+    /// Message text with hashtag entities at the given UTF-16 offsets and lengths
+    fn text(text: &str, hashtags: &[(usize, usize)]) -> MediaText {
+        MediaText {
+            text: text.to_string(),
+            entities: hashtags
+                .iter()
+                .map(|(offset, length)| {
+                    MessageEntity::new(MessageEntityKind::Hashtag, *offset, *length)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    /// Test that hashtags become tags and are cut out of the content
+    fn test_parse_hashtags() {
+        let entry = Entry::parse(&text(
+            "https://example.com #Horror #short",
+            &[(20, 7), (28, 6)],
+        ));
+
+        assert_eq!(
+            entry,
+            Entry {
+                content: "https://example.com".to_string(),
+                category: None,
+                tags: vec!["horror".to_string(), "short".to_string()],
+                links: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    /// Test that offsets of entities are counted in UTF-16, as Telegram does
+    fn test_parse_non_ascii() {
+        let entry = Entry::parse(&text("Фильм 🎬 #ужасы про #зомби", &[(9, 6), (20, 6)]));
+
+        assert_eq!(entry.content, "Фильм 🎬 про");
+        assert_eq!(entry.tags, ["ужасы", "зомби"]);
+    }
+
+    #[test]
+    /// Test that the category prefix is recognized, but URL schemes aren't categories
+    fn test_parse_category() {
+        let entry = Entry::parse(&text("Movie: https://example.com #horror", &[(27, 7)]));
+        assert_eq!(entry.category, Some(Category::Movie));
+        assert_eq!(entry.content, "https://example.com");
+
+        let entry = Entry::parse(&text("https://example.com", &[]));
+        assert_eq!(entry.category, None);
+        assert_eq!(entry.content, "https://example.com");
+
+        let entry = Entry::parse(&text("Note: buy popcorn", &[]));
+        assert_eq!(entry.category, None);
+        assert_eq!(entry.content, "Note: buy popcorn");
+    }
+
+    #[test]
+    /// Test that links are collected from plain links and links behind the text
+    fn test_parse_links() {
+        let entry = Entry::parse(&MediaText {
+            text: "Trailer and imdb.com/title/tt0066921".to_string(),
+            entities: vec![
+                MessageEntity::text_link(Url::parse("https://youtu.be/dQw4w9WgXcQ").unwrap(), 0, 7),
+                MessageEntity::new(MessageEntityKind::Url, 12, 24),
+            ],
+        });
+
+        assert_eq!(entry.content, "Trailer and imdb.com/title/tt0066921");
+        assert_eq!(
+            entry.links,
+            [
+                Url::parse("https://youtu.be/dQw4w9WgXcQ").unwrap(),
+                Url::parse("https://imdb.com/title/tt0066921").unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that messages with nothing but hashtags are kept
+    fn test_parse_only_hashtags() {
+        let entry = Entry::parse(&text("#horror", &[(0, 7)]));
+
+        assert_eq!(entry.content, "#horror");
+        assert_eq!(entry.tags, ["horror"]);
+    }
+}
@@ -1,7 +1,18 @@
-use teloxide::types::{CallbackQuery, MediaKind, MediaText, Message, MessageKind, User};
+use teloxide::types::{
+    CallbackQuery, ForwardedFrom, MediaKind, MediaText, Message, MessageId, MessageKind, User,
+};
+
+use crate::content_item::{Attachment, AttachmentKind, ForwardOrigin};
 
 use super::callbacks::Callback;
 
+/// Text of the message, or the caption of the file sent with it
+#[derive(Debug, Clone)]
+pub struct MessageContent {
+    pub text: MediaText,
+    pub attachment: Option<Attachment>,
+}
+
 /// Extract author from Message
 pub(super) fn get_message_author(msg: Message) -> Option<User> {
     if let MessageKind::Common(msg) = msg.kind {
@@ -11,15 +22,100 @@ pub(super) fn get_message_author(msg: Message) -> Option<User> {
     None
 }
 
-/// Extract message text from Message
-pub(super) fn get_message_text(msg: Message) -> Option<MediaText> {
-    if let MessageKind::Common(msg) = msg.kind {
-        if let MediaKind::Text(text) = msg.media_kind {
-            return Some(text);
+/// Extract message text from Message, photos, videos and documents are accepted too.
+///
+/// Files without a caption get their name or kind as the text, so they aren't blank in lists.
+pub(super) fn get_message_content(msg: Message) -> Option<MessageContent> {
+    let MessageKind::Common(msg) = msg.kind else {
+        return None;
+    };
+
+    let (kind, file_id, caption, entities, fallback) = match msg.media_kind {
+        MediaKind::Text(text) => {
+            return Some(MessageContent {
+                text,
+                attachment: None,
+            })
         }
-    }
+        MediaKind::Photo(photo) => (
+            AttachmentKind::Photo,
+            // sizes go from the smallest to the largest
+            photo.photo.last()?.file.id.clone(),
+            photo.caption,
+            photo.caption_entities,
+            None,
+        ),
+        MediaKind::Video(video) => (
+            AttachmentKind::Video,
+            video.video.file.id,
+            video.caption,
+            video.caption_entities,
+            video.video.file_name,
+        ),
+        MediaKind::Document(document) => (
+            AttachmentKind::Document,
+            document.document.file.id,
+            document.caption,
+            document.caption_entities,
+            document.document.file_name,
+        ),
+        _ => return None,
+    };
 
-    None
+    let text = match caption {
+        Some(caption) => MediaText {
+            text: caption,
+            entities,
+        },
+        None => MediaText {
+            text: fallback.unwrap_or_else(|| kind.as_str().to_string()),
+            entities: Vec::new(),
+        },
+    };
+
+    Some(MessageContent {
+        text,
+        attachment: Some(Attachment { kind, file_id }),
+    })
+}
+
+/// Extract where the forwarded message comes from
+pub(super) fn get_forward_origin(msg: &Message) -> Option<ForwardOrigin> {
+    let forward = msg.forward()?;
+
+    let origin = match &forward.from {
+        ForwardedFrom::User(user) => ForwardOrigin {
+            author: match &user.username {
+                Some(username) => format!("@{username}"),
+                None => user.full_name(),
+            },
+            chat_id: None,
+            message_id: None,
+        },
+        ForwardedFrom::Chat(chat) => {
+            let title = chat
+                .title()
+                .map(str::to_string)
+                .or_else(|| chat.username().map(|username| format!("@{username}")))
+                .unwrap_or_else(|| chat.id.to_string());
+
+            ForwardOrigin {
+                author: match &forward.signature {
+                    Some(signature) => format!("{title} ({signature})"),
+                    None => title,
+                },
+                chat_id: Some(chat.id),
+                message_id: forward.message_id.map(MessageId),
+            }
+        }
+        ForwardedFrom::SenderName(name) => ForwardOrigin {
+            author: name.clone(),
+            chat_id: None,
+            message_id: None,
+        },
+    };
+
+    Some(origin)
 }
 
 /// Extract callback data from CallbackQuery
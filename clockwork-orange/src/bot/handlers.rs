@@ -6,30 +6,55 @@ use color_eyre::{
     Result,
 };
 use teloxide::{
+    payloads::{AnswerCallbackQuerySetters, EditMessageReplyMarkupSetters, SendMessageSetters},
     requests::Requester,
-    types::{CallbackQuery, ChatAction, Me, MediaText, Message, MessageKind, Update, User},
+    types::{
+        CallbackQuery, ChatAction, ChatId, InlineKeyboardMarkup, InputFile, Me, Message, MessageId,
+        MessageKind, Update, User, UserId,
+    },
 };
+use time::OffsetDateTime;
 use tracing::info;
 
 use crate::{
-    content_item::ContentItem,
-    storage::{Storage, StorageBackend},
+    classifier::Classifier,
+    content_item::{ContentItem, Source},
+    export::{self, Format},
+    storage::{Key, ListId, Storage, StorageBackend},
 };
 
-use super::{callbacks::Callback, send_item_to_chat, Bot, Command};
+use super::{
+    callbacks::Callback,
+    duplicates, edit_item_message, edit_message,
+    entry::Entry,
+    extractors::{self, MessageContent},
+    history::{self, HistoryQuery},
+    list::{self, ListView},
+    random::{self, RandomSessions, Session},
+    random_keyboard, send_item_to_chat, snooze, Bot, Command,
+};
+
+/// Reply to unknown arguments of `/random` and `/random_settings`
+const RANDOM_USAGE: &str = "Unknown arguments, try /random older, /random alternate, \
+    /random max-age=30d, /random no-repeat=7d, /random me, /random @username or /random #tag. \
+    Save defaults with /random_settings, e.g. /random_settings older no-repeat=7d, \
+    or /random_settings reset";
 
 #[tracing::instrument(
-    skip(bot, storage, msg, author),
+    skip(bot, storage, sessions, msg, author),
     fields(chat_id = msg.chat.id.0, author = author.id.0),
 )]
 pub async fn handle_command<B: StorageBackend + Debug>(
     bot: Bot,
-    storage: Storage<B>,
+    mut storage: Storage<B>,
+    sessions: RandomSessions,
     msg: Message,
     author: User,
     command: Command,
 ) -> Result<()> {
     let chat_id = msg.chat.id;
+    let list = ListId::from(chat_id);
+    let author_id = author.id;
     let author = author
         .username
         .clone()
@@ -63,77 +88,192 @@ pub async fn handle_command<B: StorageBackend + Debug>(
             .wrap_err("Failed to send welcome message in /start handler")?;
         }
         Command::AllMy => {
-            let items = storage.get_user_items(&author).await?;
-
-            if items.is_empty() {
-                bot.send_message(chat_id, "You have no entries yet")
-                    .await
-                    .wrap_err("Failed to send message in /all_my handler")?;
-                return Ok(());
-            }
-
-            for (key, item) in items.iter() {
-                bot.send_chat_action(chat_id, ChatAction::Typing)
-                    .await
-                    .wrap_err("Failed to send chat action in /all_my handler")?;
-                send_item_to_chat(&bot, item, key, chat_id)
+            let (text, keyboard) =
+                list_page(&*storage, &list, &ListView::AllMy(author_id), &author, 0)
                     .await
-                    .wrap_err("Failed to send item in /all_my handler")?;
-
-                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
-            }
-
-            bot.send_message(chat_id, tg_escape("That's all!"))
+                    .wrap_err("Failed to get items in /all_my handler")?;
+            bot.send_message(chat_id, text)
+                .reply_markup(keyboard)
                 .await
-                .wrap_err("Failed to send finalizing message in /all_my handler")?;
+                .wrap_err("Failed to send list in /all_my handler")?;
         }
-        Command::Random => {
+        Command::Random(args) => {
+            let settings = storage
+                .get_settings(&list)
+                .await
+                .wrap_err("Failed to get settings in /random handler")?;
+            let Some(options) = random::parse_options(&args, &settings.random, author_id, &author)
+            else {
+                bot.send_message(chat_id, tg_escape(RANDOM_USAGE))
+                    .await
+                    .wrap_err(
+                        "Failed to send message about unknown arguments in /random handler",
+                    )?;
+                return Ok(());
+            };
+
             bot.send_chat_action(chat_id, ChatAction::Typing)
                 .await
                 .wrap_err("Failed to send chat action in /random handler")?;
 
             let item = storage
-                .get_random()
+                .get_random(&list, &options)
                 .await
                 .wrap_err("Failed to get random item in /random handler")?;
 
             match item {
                 Some((key, item)) => {
-                    send_item_to_chat(&bot, &item, &key, chat_id)
+                    storage
+                        .mark_as_shown(&list, &key)
+                        .await
+                        .wrap_err("Failed to mark item as shown in /random handler")?;
+                    let message = bot
+                        .send_message(chat_id, item.to_tg_message_text())
+                        .reply_markup(random_keyboard(&item, &key))
                         .await
                         .wrap_err("Failed to send item in /random handler")?;
+                    sessions.put(chat_id, message.id, Session::new(&key, options));
                 }
                 None => {
-                    bot.send_message(chat_id, "You have no entries yet")
+                    let text = if args.trim().is_empty() && settings.random == Default::default() {
+                        "You have no entries yet"
+                    } else {
+                        "Nothing matches, try /random without arguments or check /random_settings"
+                    };
+                    bot.send_message(chat_id, tg_escape(text))
                         .await
                         .wrap_err("Failed to send message about empty queue in /random handler")?;
                 }
             }
         }
-        Command::Unread => {
-            let items = storage.get_all().await?;
+        Command::RandomSettings(args) => {
+            let mut settings = storage
+                .get_settings(&list)
+                .await
+                .wrap_err("Failed to get settings in /random_settings handler")?;
 
-            if items.is_empty() {
-                bot.send_message(chat_id, "You have no entries yet")
+            let text = if args.trim().is_empty() {
+                random::describe_settings(&settings.random)
+            } else if let Some(random) = random::parse_settings(&args, &settings.random) {
+                settings.random = random;
+                storage
+                    .set_settings(&list, &settings)
                     .await
-                    .wrap_err("Failed to send message about empty queue in /unread handler")?;
+                    .wrap_err("Failed to save settings in /random_settings handler")?;
+                format!("Saved!\n{}", random::describe_settings(&settings.random))
+            } else {
+                RANDOM_USAGE.to_string()
+            };
+
+            bot.send_message(chat_id, tg_escape(&text))
+                .await
+                .wrap_err("Failed to send settings in /random_settings handler")?;
+        }
+        Command::Snooze(args) => {
+            let key = msg.reply_to_message().and_then(snooze::item_key);
+            let now = storage.get_now().await?;
+
+            let text = match (key, snooze::parse_until(&args, now)) {
+                (Some(key), Some(until)) => {
+                    storage
+                        .snooze(&list, &key, until)
+                        .await
+                        .wrap_err("Failed to snooze item in /snooze handler")?;
+                    format!("Snoozed until {} ⏰ Find it in /unread", until.date())
+                }
+                _ => "Reply to the item with /snooze 10d, /snooze 2w or /snooze 2023-12-31"
+                    .to_string(),
+            };
+
+            bot.send_message(chat_id, tg_escape(&text))
+                .reply_to_message_id(msg.id)
+                .await
+                .wrap_err("Failed to send reply in /snooze handler")?;
+        }
+        Command::Unread(args) => {
+            let Some(view) = ListView::parse_unread(&args) else {
+                bot.send_message(
+                    chat_id,
+                    tg_escape("Try /unread or /unread #tag, very long tags aren't supported here"),
+                )
+                .await
+                .wrap_err("Failed to send message about unknown tag in /unread handler")?;
                 return Ok(());
-            }
+            };
 
-            for (key, item) in items.iter() {
-                bot.send_chat_action(chat_id, ChatAction::Typing)
-                    .await
-                    .wrap_err("Failed to send chat action in /unread handler")?;
-                send_item_to_chat(&bot, item, key, chat_id)
-                    .await
-                    .wrap_err("Failed to send item in /unread handler")?;
+            let (text, keyboard) = list_page(&*storage, &list, &view, &author, 0)
+                .await
+                .wrap_err("Failed to get items in /unread handler")?;
+            bot.send_message(chat_id, text)
+                .reply_markup(keyboard)
+                .await
+                .wrap_err("Failed to send list in /unread handler")?;
+        }
+        Command::Archive => {
+            let (text, keyboard) = list_page(&*storage, &list, &ListView::Archive, &author, 0)
+                .await
+                .wrap_err("Failed to get items in /archive handler")?;
+            bot.send_message(chat_id, text)
+                .reply_markup(keyboard)
+                .await
+                .wrap_err("Failed to send list in /archive handler")?;
+        }
+        Command::Export(format) => {
+            let format = if format.trim().is_empty() {
+                Format::default()
+            } else {
+                match format.parse::<Format>() {
+                    Ok(format) => format,
+                    Err(_) => {
+                        bot.send_message(
+                            chat_id,
+                            tg_escape("Unknown format, try /export json or /export csv"),
+                        )
+                        .await
+                        .wrap_err(
+                            "Failed to send message about unknown format in /export handler",
+                        )?;
+                        return Ok(());
+                    }
+                }
+            };
 
-                tokio::time::sleep(std::time::Duration::from_millis(250)).await;
-            }
+            bot.send_chat_action(chat_id, ChatAction::UploadDocument)
+                .await
+                .wrap_err("Failed to send chat action in /export handler")?;
+
+            let records = export::export(&*storage, &[list])
+                .await
+                .wrap_err("Failed to export items in /export handler")?;
+            let mut dump = Vec::new();
+            export::write_records(&records, format, &mut dump)?;
 
-            bot.send_message(chat_id, tg_escape("That's all!"))
+            let file_name = format!("clockwork-orange-{list}.{}", format.extension());
+            bot.send_document(chat_id, InputFile::memory(dump).file_name(file_name))
                 .await
-                .wrap_err("Failed to send finalizing message in /unread handler")?;
+                .wrap_err("Failed to send document in /export handler")?;
+        }
+        Command::History(args) => {
+            let Some(query) = HistoryQuery::parse(&args, author_id) else {
+                bot.send_message(
+                    chat_id,
+                    tg_escape(
+                        "Unknown period, try /history week, /history 30d, /history 2023-10 \
+                        or /history 2023, add \"me\" to see only your suggestions",
+                    ),
+                )
+                .await
+                .wrap_err("Failed to send message about unknown period in /history handler")?;
+                return Ok(());
+            };
+
+            let (text, keyboard) = history_page(&*storage, &list, &query, &author, 0)
+                .await
+                .wrap_err("Failed to get history in /history handler")?;
+            bot.send_message(chat_id, text)
+                .reply_markup(keyboard)
+                .await
+                .wrap_err("Failed to send history in /history handler")?;
         }
     }
 
@@ -143,44 +283,472 @@ pub async fn handle_command<B: StorageBackend + Debug>(
 pub async fn handle_callback<B: StorageBackend>(
     bot: Bot,
     mut storage: Storage<B>,
+    classifier: Classifier,
+    sessions: RandomSessions,
     update: Update,
     callback_query: CallbackQuery,
     callback: Callback,
 ) -> Result<()> {
     let chat_id = update.chat().ok_or_else(|| eyre!("No chat in update"))?.id;
+    let list = ListId::from(chat_id);
+    // short text shown to the user on top of the chat, for feedback without extra messages
+    let mut notification = None;
 
     match callback {
         Callback::MarkAsRead(key) => {
-            storage
-                .mark_as_read(&key)
+            let item = storage
+                .mark_as_read(&list, &key)
                 .await
                 .wrap_err("Marking as read failed")?;
 
-            bot.send_message(chat_id, tg_escape("Great! I hope you liked it 😊"))
+            if let Some(message) = &callback_query.message {
+                edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+            }
+            notification = Some("Great! I hope you liked it 😊");
+        }
+        Callback::MarkAsUnread(key) => {
+            let item = storage
+                .mark_as_unread(&list, &key)
+                .await
+                .wrap_err("Marking as unread failed")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+            }
+            notification = Some("Okay, it's back in the queue 📥");
+        }
+        Callback::Delete(key) => {
+            let mut request = bot
+                .send_message(
+                    chat_id,
+                    tg_escape("Delete this item? It'll be moved to the /archive, where you can restore it"),
+                )
+                .reply_markup(InlineKeyboardMarkup::new(vec![vec![
+                    Callback::confirm_delete(&key).as_button("🗑 Yes, delete"),
+                    Callback::CancelDelete.as_button("Cancel"),
+                ]]));
+            if let Some(message) = &callback_query.message {
+                request = request.reply_to_message_id(message.id);
+            }
+
+            request
+                .await
+                .wrap_err("Failed to ask for delete confirmation")?;
+        }
+        Callback::ConfirmDelete(key) => {
+            let item = storage
+                .archive(&list, &key)
+                .await
+                .wrap_err("Moving to archive failed")?;
+
+            let text = tg_escape("Deleted 🗃 Use /archive if you change your mind");
+            match &callback_query.message {
+                Some(prompt) => {
+                    bot.edit_message_text(chat_id, prompt.id, text)
+                        .await
+                        .wrap_err("Failed to edit delete confirmation")?;
+                    // the prompt replies to the item message, which gets the Restore button
+                    if let Some(message) = prompt.reply_to_message() {
+                        edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+                    }
+                }
+                None => {
+                    bot.send_message(chat_id, text)
+                        .await
+                        .wrap_err("Failed to notify user that item was deleted")?;
+                }
+            }
+        }
+        Callback::CancelDelete => {
+            if let Some(prompt) = &callback_query.message {
+                bot.delete_message(chat_id, prompt.id)
+                    .await
+                    .wrap_err("Failed to delete confirmation message")?;
+            }
+        }
+        Callback::Restore(key) => {
+            let item = storage
+                .restore(&list, &key)
+                .await
+                .wrap_err("Restoring from archive failed")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+            }
+            notification = Some("Restored! It's back in the list 📥");
+        }
+        Callback::History { page, query } => {
+            let username = username_of(query.author, &callback_query);
+            let (text, keyboard) = history_page(&*storage, &list, &query, &username, page)
                 .await
-                .wrap_err(
-                    "Failed to notify user that we've got \"watched\" item status from him",
-                )?;
+                .wrap_err("Failed to get history page")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_message(&bot, chat_id, message.id, text, keyboard)
+                    .await
+                    .wrap_err("Failed to switch history page")?;
+            }
+        }
+        Callback::Show(key) => match storage.get(&list, &key).await? {
+            Some(item) => {
+                send_item_to_chat(&bot, &item, &key, chat_id)
+                    .await
+                    .wrap_err("Failed to send item from the list")?;
+            }
+            None => notification = Some("This item is gone"),
+        },
+        Callback::RandomAnother(key) => {
+            if let Some(message) = &callback_query.message {
+                let session =
+                    resume_session(&*storage, &sessions, chat_id, message.id, &key).await?;
+                notification = reroll(&bot, &mut *storage, &sessions, chat_id, message.id, session)
+                    .await
+                    .wrap_err("Failed to show another random item")?;
+            }
+        }
+        Callback::RandomSnooze(key) => {
+            if let Some(message) = &callback_query.message {
+                let now = storage.get_now().await?;
+                storage
+                    .snooze(
+                        &list,
+                        &key,
+                        snooze::days_from(now, snooze::RANDOM_SNOOZE_DAYS),
+                    )
+                    .await
+                    .wrap_err("Snoozing random item failed")?;
+
+                let mut session =
+                    resume_session(&*storage, &sessions, chat_id, message.id, &key).await?;
+                session.snooze(&key);
+                notification = reroll(&bot, &mut *storage, &sessions, chat_id, message.id, session)
+                    .await
+                    .wrap_err("Failed to show random item instead of snoozed one")?
+                    .or(Some("Snoozed for a week ⏰ Find it in /unread"));
+            }
+        }
+        Callback::RandomWatched(key) => {
+            let item = storage
+                .mark_as_read(&list, &key)
+                .await
+                .wrap_err("Marking random item as read failed")?;
+
+            if let Some(message) = &callback_query.message {
+                sessions.finish(chat_id, message.id);
+                edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+            }
+            notification = Some("Great! I hope you liked it 😊");
+        }
+        Callback::List { view, page } => {
+            let username = username_of(view.author(), &callback_query);
+            let (text, keyboard) = list_page(&*storage, &list, &view, &username, page)
+                .await
+                .wrap_err("Failed to get list page")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_message(&bot, chat_id, message.id, text, keyboard)
+                    .await
+                    .wrap_err("Failed to switch list page")?;
+            }
+        }
+        Callback::ListMarkAsRead { view, page, key } => {
+            storage
+                .mark_as_read(&list, &key)
+                .await
+                .wrap_err("Marking as read from the list failed")?;
+
+            let username = username_of(view.author(), &callback_query);
+            let (text, keyboard) = list_page(&*storage, &list, &view, &username, page)
+                .await
+                .wrap_err("Failed to get list page")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_message(&bot, chat_id, message.id, text, keyboard)
+                    .await
+                    .wrap_err("Failed to refresh the list")?;
+            }
+            notification = Some("Great! I hope you liked it 😊 Find it in /history");
+        }
+        Callback::ListRestore { page, key } => {
+            storage
+                .restore(&list, &key)
+                .await
+                .wrap_err("Restoring from the archive list failed")?;
+
+            let (text, keyboard) = list_page(&*storage, &list, &ListView::Archive, "", page)
+                .await
+                .wrap_err("Failed to get archive page")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_message(&bot, chat_id, message.id, text, keyboard)
+                    .await
+                    .wrap_err("Failed to refresh the archive")?;
+            }
+            notification = Some("Restored! It's back in the list 📥");
+        }
+        Callback::SnoozeMenu(key) => {
+            let item = storage
+                .get(&list, &key)
+                .await
+                .wrap_err("Failed to get item for snooze menu")?;
+            let now = storage.get_now().await?;
+
+            match (item, &callback_query.message) {
+                (Some(item), Some(message)) => {
+                    bot.edit_message_reply_markup(chat_id, message.id)
+                        .reply_markup(snooze::menu_keyboard(&key, item.is_snoozed(now)))
+                        .await
+                        .wrap_err("Failed to show snooze menu")?;
+                }
+                (Some(_), None) => {}
+                (None, _) => notification = Some("This item is gone"),
+            }
+        }
+        Callback::Snooze { key, days } => {
+            let now = storage.get_now().await?;
+            let item = storage
+                .snooze(&list, &key, snooze::days_from(now, days))
+                .await
+                .wrap_err("Snoozing failed")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+            }
+            notification = Some("Snoozed ⏰ Find it in /unread");
+        }
+        Callback::Unsnooze(key) => {
+            let item = storage
+                .unsnooze(&list, &key)
+                .await
+                .wrap_err("Waking item up failed")?;
+
+            if let Some(message) = &callback_query.message {
+                edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+            }
+            notification = Some("It's back in /random 🔔");
+        }
+        Callback::SnoozeCancel(key) => {
+            let item = storage
+                .get(&list, &key)
+                .await
+                .wrap_err("Failed to get item to close snooze menu")?;
+
+            match (item, &callback_query.message) {
+                (Some(item), Some(message)) => {
+                    edit_item_message(&bot, &item, &key, chat_id, message.id).await?;
+                }
+                (Some(_), None) => {}
+                (None, _) => notification = Some("This item is gone"),
+            }
+        }
+        Callback::Original(key) => {
+            let source = storage
+                .get(&list, &key)
+                .await
+                .wrap_err("Failed to get item to send the original message")?
+                .map(|item| (item.source(), item.forward_origin().is_some()));
+
+            notification = match source {
+                Some((Some(source), is_forward)) => {
+                    // forwards keep the original author, own messages are copied without
+                    // the "forwarded from" header
+                    let sent = if is_forward {
+                        bot.forward_message(chat_id, source.chat_id, source.message_id)
+                            .await
+                            .map(|_| ())
+                    } else {
+                        bot.copy_message(chat_id, source.chat_id, source.message_id)
+                            .await
+                            .map(|_| ())
+                    };
+
+                    match sent {
+                        Ok(()) => None,
+                        Err(err) => {
+                            info!("Failed to send the original message: {err}");
+                            Some("The original message is gone")
+                        }
+                    }
+                }
+                Some((None, _)) => Some("This item has no original message"),
+                None => Some("This item is gone"),
+            };
+        }
+        Callback::AddAnyway => {
+            // the warning replies to the message with the new item
+            let entry = callback_query.message.as_ref().and_then(|warning| {
+                let original = warning.reply_to_message()?;
+                let author = extractors::get_message_author(original.clone())?;
+                let content = extractors::get_message_content(original.clone())?;
+
+                Some((warning.id, original, author, content))
+            });
+
+            match entry {
+                Some((warning_id, original, author, content)) => {
+                    let item = new_item(&classifier, original, author, content)?;
+                    let reply = save_item(&mut *storage, &list, item).await?;
+                    edit_message(
+                        &bot,
+                        chat_id,
+                        warning_id,
+                        tg_escape(&reply),
+                        InlineKeyboardMarkup::default(),
+                    )
+                    .await?;
+                }
+                None => notification = Some("The original message is gone"),
+            }
         }
     }
 
-    bot.answer_callback_query(&callback_query.id)
+    let mut answer = bot.answer_callback_query(&callback_query.id);
+    if let Some(text) = notification {
+        answer = answer.text(text);
+    }
+    answer
         .await
         .wrap_err("Failed to set callback answered in TG API")?;
 
     Ok(())
 }
 
+/// Get watched items for the query and render the page of them
+async fn history_page<B: StorageBackend>(
+    storage: &B,
+    list: &ListId,
+    query: &HistoryQuery,
+    username: &str,
+    page: usize,
+) -> Result<(String, InlineKeyboardMarkup)> {
+    let now = storage.get_now().await?;
+    let mut items = storage
+        .get_history(list, &query.filter(now, username))
+        .await?;
+    // recently watched first
+    items.reverse();
+
+    Ok(history::render_page(query, &items, page))
+}
+
+/// Take the session of the `/random` message out, or resume it from the `current` item
+/// with the chat settings
+async fn resume_session<B: StorageBackend>(
+    storage: &B,
+    sessions: &RandomSessions,
+    chat_id: ChatId,
+    message_id: MessageId,
+    current: &Key,
+) -> Result<Session> {
+    if let Some(session) = sessions.take(chat_id, message_id) {
+        return Ok(session);
+    }
+
+    let settings = storage.get_settings(&ListId::from(chat_id)).await?;
+    Ok(Session::new(current, settings.random.to_options()))
+}
+
+/// Show another item of the session in the `/random` message.
+///
+/// Returns notification for the user if there is nothing else to show.
+async fn reroll<B: StorageBackend>(
+    bot: &Bot,
+    storage: &mut B,
+    sessions: &RandomSessions,
+    chat_id: ChatId,
+    message_id: MessageId,
+    mut session: Session,
+) -> Result<Option<&'static str>> {
+    let list = ListId::from(chat_id);
+
+    let mut picked = None;
+    for start_over in [false, true] {
+        if let Some((key, item)) = storage
+            .get_random(&list, &session.next_options(start_over))
+            .await?
+        {
+            session.show(&key, start_over);
+            picked = Some((key, item));
+            break;
+        }
+    }
+    sessions.put(chat_id, message_id, session);
+
+    let Some((key, item)) = picked else {
+        return Ok(Some("Nothing else to pick, that's the whole list"));
+    };
+    storage.mark_as_shown(&list, &key).await?;
+    edit_message(
+        bot,
+        chat_id,
+        message_id,
+        item.to_tg_message_text(),
+        random_keyboard(&item, &key),
+    )
+    .await?;
+
+    Ok(None)
+}
+
+/// Get queued items of the view and render the page of them
+async fn list_page<B: StorageBackend>(
+    storage: &B,
+    list: &ListId,
+    view: &ListView,
+    username: &str,
+    page: usize,
+) -> Result<(String, InlineKeyboardMarkup)> {
+    let mut items = match view {
+        ListView::Unread(_) => storage.get_all(list).await?,
+        ListView::AllMy(author_id) => storage.get_user_items(list, *author_id, username).await?,
+        ListView::Archive => storage.get_archived(list).await?,
+    };
+    if let Some(tag) = view.tag() {
+        items.retain(|_, item| item.matches_tag(tag));
+    }
+    let now = storage.get_now().await?;
+    let mut items: Vec<_> = items.into_iter().collect();
+    if *view == ListView::Archive {
+        // recently deleted first
+        items.sort_by(|(a_key, a), (b_key, b)| {
+            (b.archived_at(), b_key).cmp(&(a.archived_at(), a_key))
+        });
+    } else {
+        // in the order of addition, legacy items without the date go first, snoozed ones go last
+        items.sort_by(|(a_key, a), (b_key, b)| {
+            (a.is_snoozed(now), a.added_at(), a_key).cmp(&(b.is_snoozed(now), b.added_at(), b_key))
+        });
+    }
+
+    Ok(list::render_page(view, &items, page, now))
+}
+
+/// Username to find legacy items of `author` by, when a page is switched with a button.
+///
+/// Only the username of the user who tapped the button is known, so legacy items
+/// are found only when they tap buttons of their own lists.
+fn username_of(author: Option<UserId>, callback_query: &CallbackQuery) -> String {
+    match author {
+        Some(author_id) if author_id == callback_query.from.id => callback_query
+            .from
+            .username
+            .clone()
+            .unwrap_or_else(|| author_id.to_string()),
+        _ => String::new(),
+    }
+}
+
 pub async fn add_new_entry<B: StorageBackend>(
     bot: Bot,
     me: Me,
     mut storage: Storage<B>,
+    classifier: Classifier,
     msg: Message,
     author: User,
-    text: MediaText,
+    content: MessageContent,
 ) -> Result<()> {
     let chat_id = msg.chat.id;
-    let author = author.username.unwrap_or_else(|| author.id.to_string());
+    let list = ListId::from(chat_id);
 
     // check if user is replying to someone else's message
     match msg.reply_to_message() {
@@ -198,15 +766,82 @@ pub async fn add_new_entry<B: StorageBackend>(
         }
     }
 
-    let content_item = ContentItem::new(author, text.text);
-    storage
-        .set(&msg.id.0.to_string().into(), content_item)
-        .await
-        .wrap_err("Failed to save new item from user")?;
+    let content_item = new_item(&classifier, &msg, author, content)?;
+
+    // the same link may have been added before, e.g. by someone else
+    let links = duplicates::links(&classifier, &content_item);
+    if !links.is_empty() {
+        let items = storage
+            .dump(&list)
+            .await
+            .wrap_err("Failed to get items to look for duplicates")?;
+
+        if let Some((key, item)) = duplicates::find(&items, &links, &classifier) {
+            info!("Item is a duplicate of {key:?}, asking what to do");
+            bot.send_message(chat_id, duplicates::message_text(item))
+                .reply_to_message_id(msg.id)
+                .reply_markup(duplicates::keyboard(item, key))
+                .await
+                .wrap_err("Failed to send duplicate warning")?;
+
+            return Ok(());
+        }
+    }
 
-    bot.send_message(chat_id, tg_escape("Saved! 🎉"))
+    let reply = save_item(&mut *storage, &list, content_item).await?;
+    bot.send_message(chat_id, tg_escape(&reply))
         .await
         .wrap_err("Failed to send confirmation message")?;
 
     Ok(())
 }
+
+/// Create an item from the message
+fn new_item(
+    classifier: &Classifier,
+    msg: &Message,
+    author: User,
+    content: MessageContent,
+) -> Result<ContentItem> {
+    let author_id = author.id;
+    let author = author.username.unwrap_or_else(|| author.id.to_string());
+    let added_at = OffsetDateTime::from_unix_timestamp(msg.date.timestamp())
+        .wrap_err("Failed to convert message date")?;
+
+    let entry = Entry::parse(&content.text);
+    let source = Source {
+        chat_id: msg.chat.id,
+        message_id: msg.id,
+    };
+    // links of known sources get a category and a canonical URL,
+    // but the category given explicitly wins
+    let (text, category) = classifier.apply(&entry.content);
+    let (links, links_category) = classifier.apply_links(entry.links);
+    let mut content_item = ContentItem::new(author_id, author, text, added_at);
+    content_item.set_category(entry.category.or(category).or(links_category));
+    content_item.set_tags(entry.tags);
+    content_item.set_links(links);
+    content_item.set_attachment(content.attachment);
+    content_item.set_source(Some(source));
+    content_item.set_forward_origin(extractors::get_forward_origin(msg));
+
+    Ok(content_item)
+}
+
+/// Save the new item, returning the confirmation for the user
+async fn save_item<B: StorageBackend>(
+    storage: &mut B,
+    list: &ListId,
+    content_item: ContentItem,
+) -> Result<String> {
+    let reply = match content_item.labels() {
+        Some(labels) => format!("Saved! 🎉 {labels}"),
+        None => "Saved! 🎉".to_string(),
+    };
+    storage
+        .set(list, &Key::generate(), content_item)
+        .await
+        .wrap_err("Failed to save new item from user")?;
+
+    Ok(reply)
+}
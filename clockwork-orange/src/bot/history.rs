@@ -0,0 +1,383 @@
+//! `/history` arguments and pages.
+//!
+//! Query is kept in the payload of the page buttons, so it has a compact
+//! text form: `<period>:<author id>`, where author id is empty when the history
+//! isn't filtered by author. Calendar periods are in UTC.
+
+use clockwork_orange_messages::tg_escape;
+use teloxide::types::{InlineKeyboardMarkup, UserId};
+use time::{Date, Duration, Month, OffsetDateTime};
+
+use crate::{
+    content_item::ContentItem,
+    storage::{HistoryFilter, Key},
+};
+
+use super::{callbacks::Callback, pages};
+
+/// Number of items on a single page
+const PAGE_SIZE: usize = 10;
+
+/// Time range of the history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    All,
+    /// Last N days
+    Days(u16),
+    Month(i32, Month),
+    Year(i32),
+}
+
+impl Period {
+    /// Parse period in the payload format: `all`, `7d`, `2023-10` or `2023`.
+    ///
+    /// Months and years out of the supported dates are rejected.
+    fn parse(s: &str) -> Option<Self> {
+        if s == "all" {
+            return Some(Self::All);
+        }
+        if let Some(days) = s.strip_suffix('d') {
+            return days.parse().ok().filter(|days| *days > 0).map(Self::Days);
+        }
+
+        let period = match s.split_once('-') {
+            Some((year, month)) => Self::Month(
+                year.parse().ok()?,
+                Month::try_from(month.parse::<u8>().ok()?).ok()?,
+            ),
+            None => Self::Year(s.parse().ok()?),
+        };
+        period.calendar_range().map(|_| period)
+    }
+
+    fn to_payload(self) -> String {
+        match self {
+            Self::All => "all".to_string(),
+            Self::Days(days) => format!("{days}d"),
+            Self::Month(year, month) => format!("{year}-{:02}", month as u8),
+            Self::Year(year) => year.to_string(),
+        }
+    }
+
+    /// Get `[from, to)` range of the period
+    fn range(self, now: OffsetDateTime) -> (Option<OffsetDateTime>, Option<OffsetDateTime>) {
+        match self {
+            Self::All => (None, None),
+            Self::Days(days) => (Some(now - Duration::days(days.into())), None),
+            Self::Month(..) | Self::Year(_) => self.calendar_range().unzip(),
+        }
+    }
+
+    /// Get `[from, to)` range of the month or the year,
+    /// `None` if it's out of the supported dates or the period isn't a calendar one
+    fn calendar_range(self) -> Option<(OffsetDateTime, OffsetDateTime)> {
+        let start_of = |year, month| {
+            Date::from_calendar_date(year, month, 1)
+                .ok()
+                .map(|date| date.midnight().assume_utc())
+        };
+
+        match self {
+            Self::All | Self::Days(_) => None,
+            Self::Month(year, month) => {
+                let next_year = if month == Month::December {
+                    year.checked_add(1)?
+                } else {
+                    year
+                };
+
+                Some((start_of(year, month)?, start_of(next_year, month.next())?))
+            }
+            Self::Year(year) => Some((
+                start_of(year, Month::January)?,
+                start_of(year.checked_add(1)?, Month::January)?,
+            )),
+        }
+    }
+
+    fn describe(self) -> String {
+        match self {
+            Self::All => "all time".to_string(),
+            Self::Days(1) => "the last day".to_string(),
+            Self::Days(days) => format!("the last {days} days"),
+            Self::Month(year, month) => format!("{month} {year}"),
+            Self::Year(year) => year.to_string(),
+        }
+    }
+}
+
+/// Which part of the history to show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryQuery {
+    pub period: Period,
+    /// Show only items added by this user
+    pub author: Option<UserId>,
+}
+
+impl HistoryQuery {
+    /// Parse `/history` arguments, returning `None` for unknown ones.
+    ///
+    /// Accepts a period (`week`, `month`, `year`, `7d`, `2023-10` or `2023`)
+    /// and `me` to show only items added by `user_id`, in any order.
+    pub fn parse(args: &str, user_id: UserId) -> Option<Self> {
+        let mut query = Self {
+            period: Period::All,
+            author: None,
+        };
+
+        for arg in args.split_whitespace() {
+            match arg.to_lowercase().as_str() {
+                "me" | "mine" => query.author = Some(user_id),
+                "week" => query.period = Period::Days(7),
+                "month" => query.period = Period::Days(30),
+                "year" => query.period = Period::Days(365),
+                arg => query.period = Period::parse(arg)?,
+            }
+        }
+
+        Some(query)
+    }
+
+    pub fn to_payload(self) -> String {
+        let author = self
+            .author
+            .map(|author| author.to_string())
+            .unwrap_or_default();
+
+        format!("{}:{author}", self.period.to_payload())
+    }
+
+    pub fn from_payload(payload: &str) -> Option<Self> {
+        let (period, author) = payload.split_once(':')?;
+        let author = match author {
+            "" => None,
+            author => Some(UserId(author.parse().ok()?)),
+        };
+
+        Some(Self {
+            period: Period::parse(period)?,
+            author,
+        })
+    }
+
+    /// Build storage filter, `username` of the author is used to find legacy items
+    pub fn filter(&self, now: OffsetDateTime, username: &str) -> HistoryFilter {
+        let (from, to) = self.period.range(now);
+
+        HistoryFilter {
+            from,
+            to,
+            author: self.author.map(|author| (author, username.to_string())),
+        }
+    }
+
+    fn describe(&self) -> String {
+        let period = self.period.describe();
+        match self.author {
+            Some(_) => format!("{period}, only your suggestions"),
+            None => period,
+        }
+    }
+}
+
+/// Render page of the history – `items` should be sorted newest first.
+///
+/// Returns message text, escaped for Telegram, and buttons to switch pages.
+pub fn render_page(
+    query: &HistoryQuery,
+    items: &[(Key, ContentItem)],
+    page: usize,
+) -> (String, InlineKeyboardMarkup) {
+    if items.is_empty() {
+        let text = format!("Nothing was watched during {}", query.describe());
+
+        return (tg_escape(&text), InlineKeyboardMarkup::default());
+    }
+
+    let (page, pages) = pages::clamp(items.len(), page, PAGE_SIZE);
+
+    let mut text = format!(
+        "📜 Watched {} items during {}, page {}/{pages}:\n",
+        items.len(),
+        query.describe(),
+        page + 1,
+    );
+    for (index, (_, item)) in items
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+    {
+        let content = pages::shorten(item.content());
+        let read_at = item
+            .read_at()
+            .map(|read_at| format!(" on {}", read_at.date()))
+            .unwrap_or_default();
+        text.push_str(&format!(
+            "\n{}. {content} – @{}{read_at}",
+            index + 1,
+            item.author()
+        ));
+    }
+
+    let navigation = pages::navigation(page, pages, |page| Callback::history(page, query));
+    let keyboard = if navigation.is_empty() {
+        InlineKeyboardMarkup::default()
+    } else {
+        InlineKeyboardMarkup::new(vec![navigation])
+    };
+
+    (tg_escape(&text), keyboard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: Month, day: u8) -> OffsetDateTime {
+        Date::from_calendar_date(year, month, day)
+            .unwrap()
+            .midnight()
+            .assume_utc()
+    }
+
+    #[test]
+    /// Test that arguments are parsed in any order and unknown ones are rejected
+    fn test_parse() {
+        let user = UserId(1);
+
+        assert_eq!(
+            HistoryQuery::parse("", user),
+            Some(HistoryQuery {
+                period: Period::All,
+                author: None
+            })
+        );
+        assert_eq!(
+            HistoryQuery::parse("me 2023-10", user),
+            Some(HistoryQuery {
+                period: Period::Month(2023, Month::October),
+                author: Some(user)
+            })
+        );
+        assert_eq!(
+            HistoryQuery::parse("Week", user).unwrap().period,
+            Period::Days(7)
+        );
+        assert_eq!(
+            HistoryQuery::parse("14d", user).unwrap().period,
+            Period::Days(14)
+        );
+        assert_eq!(
+            HistoryQuery::parse("2023", user).unwrap().period,
+            Period::Year(2023)
+        );
+
+        assert_eq!(HistoryQuery::parse("yesterday", user), None);
+        assert_eq!(HistoryQuery::parse("2023-13", user), None);
+        assert_eq!(HistoryQuery::parse("0d", user), None);
+
+        // out of the supported dates
+        assert_eq!(HistoryQuery::parse("99999", user), None);
+        assert_eq!(HistoryQuery::parse("99999-01", user), None);
+        assert_eq!(HistoryQuery::parse("2147483647", user), None);
+        assert_eq!(HistoryQuery::parse("9999-12", user), None);
+        assert_eq!(
+            HistoryQuery::parse("9999-11", user).unwrap().period,
+            Period::Month(9999, Month::November)
+        );
+    }
+
+    #[test]
+    /// Test that queries survive the trip through the button payload
+    fn test_payload_roundtrip() {
+        for query in [
+            HistoryQuery {
+                period: Period::All,
+                author: None,
+            },
+            HistoryQuery {
+                period: Period::Days(30),
+                author: Some(UserId(123456789)),
+            },
+            HistoryQuery {
+                period: Period::Month(2023, Month::January),
+                author: None,
+            },
+            HistoryQuery {
+                period: Period::Year(2024),
+                author: Some(UserId(1)),
+            },
+        ] {
+            assert_eq!(HistoryQuery::from_payload(&query.to_payload()), Some(query));
+        }
+    }
+
+    #[test]
+    /// Test that periods are converted to half-open ranges
+    fn test_range() {
+        let now = date(2024, Month::March, 15);
+
+        assert_eq!(Period::All.range(now), (None, None));
+        assert_eq!(
+            Period::Days(14).range(now),
+            (Some(date(2024, Month::March, 1)), None)
+        );
+        assert_eq!(
+            Period::Month(2023, Month::December).range(now),
+            (
+                Some(date(2023, Month::December, 1)),
+                Some(date(2024, Month::January, 1))
+            )
+        );
+        assert_eq!(
+            Period::Year(2023).range(now),
+            (
+                Some(date(2023, Month::January, 1)),
+                Some(date(2024, Month::January, 1))
+            )
+        );
+    }
+
+    #[test]
+    /// Test that pages have buttons to their neighbours only
+    fn test_render_page() {
+        let query = HistoryQuery {
+            period: Period::All,
+            author: None,
+        };
+        let items: Vec<_> = (0..25)
+            .map(|i| {
+                let mut item = ContentItem::legacy("alice", format!("item {i}"));
+                item.set_read(date(2024, Month::March, 1));
+                (Key::generate(), item)
+            })
+            .collect();
+        let buttons = |keyboard: InlineKeyboardMarkup| -> Vec<String> {
+            keyboard
+                .inline_keyboard
+                .into_iter()
+                .flatten()
+                .map(|button| button.text)
+                .collect()
+        };
+
+        let (text, keyboard) = render_page(&query, &items, 0);
+        assert!(text.contains("page 1/3"));
+        assert!(text.contains("item 9"));
+        assert!(!text.contains("item 10"));
+        assert_eq!(buttons(keyboard), ["Next ➡️"]);
+
+        let (_, keyboard) = render_page(&query, &items, 1);
+        assert_eq!(buttons(keyboard), ["⬅️ Prev", "Next ➡️"]);
+
+        // out of range pages are clamped, e.g. when items were restored meanwhile
+        let (text, keyboard) = render_page(&query, &items, 10);
+        assert!(text.contains("page 3/3"));
+        assert_eq!(buttons(keyboard), ["⬅️ Prev"]);
+
+        let (text, keyboard) = render_page(&query, &[], 0);
+        assert!(text.starts_with("Nothing was watched"));
+        assert!(buttons(keyboard).is_empty());
+    }
+}
@@ -0,0 +1,322 @@
+//! Lists of items – queued ones in `/unread` and `/all_my`, deleted ones in `/archive`.
+//!
+//! A list is a single message with numbered entries, buttons to show and to mark
+//! as watched (or restore, in the archive) each of them, and buttons to switch pages.
+//! The view is kept in the payload of the buttons, so the message can be re-rendered in place.
+//!
+//! Snoozed items go after the rest, under a separate heading. `/unread` can be
+//! limited to a tag, which is kept in the payload too, so it's limited to `MAX_TAG_LEN`.
+
+use clockwork_orange_messages::tg_escape;
+use teloxide::types::{InlineKeyboardMarkup, UserId};
+use time::OffsetDateTime;
+
+use crate::{
+    content_item::{normalize_tag, ContentItem},
+    storage::Key,
+};
+
+use super::{callbacks::Callback, pages};
+
+/// Number of items on a single page, every one of them has its own buttons
+const PAGE_SIZE: usize = 5;
+
+/// Longest tag `/unread` can be limited to, in bytes – the longest payload,
+/// `list-read:<page>:unread#<tag>:<key>` with a 3-digit page, should fit into 64 bytes
+pub const MAX_TAG_LEN: usize = 16;
+
+/// Which items are listed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListView {
+    /// All queued items, `/unread`, optionally only ones with the tag
+    Unread(Option<String>),
+    /// Queued items added by the user, `/all_my`
+    AllMy(UserId),
+    /// Items deleted to the archive, `/archive`
+    Archive,
+}
+
+impl ListView {
+    /// Parse arguments of `/unread` – nothing or a single tag, `#` is optional
+    pub fn parse_unread(args: &str) -> Option<Self> {
+        match args.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => Some(Self::Unread(None)),
+            [tag] => {
+                let tag = normalize_tag(tag);
+                (!tag.is_empty() && tag.len() <= MAX_TAG_LEN).then_some(Self::Unread(Some(tag)))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_payload(&self) -> String {
+        match self {
+            Self::Unread(None) => "unread".to_string(),
+            Self::Unread(Some(tag)) => format!("unread#{tag}"),
+            Self::AllMy(author) => format!("my{author}"),
+            Self::Archive => "archive".to_string(),
+        }
+    }
+
+    pub fn from_payload(payload: &str) -> Option<Self> {
+        if payload == "unread" {
+            return Some(Self::Unread(None));
+        }
+        if payload == "archive" {
+            return Some(Self::Archive);
+        }
+        if let Some(tag) = payload.strip_prefix("unread#") {
+            return Some(Self::Unread(Some(tag.to_string())));
+        }
+
+        payload
+            .strip_prefix("my")
+            .and_then(|author| author.parse().ok())
+            .map(|author| Self::AllMy(UserId(author)))
+    }
+
+    /// Author of the listed items, if the list is limited to them
+    pub fn author(&self) -> Option<UserId> {
+        match self {
+            Self::Unread(_) | Self::Archive => None,
+            Self::AllMy(author) => Some(*author),
+        }
+    }
+
+    /// Tag of the listed items, if the list is limited to it
+    pub fn tag(&self) -> Option<&str> {
+        match self {
+            Self::Unread(tag) => tag.as_deref(),
+            Self::AllMy(_) | Self::Archive => None,
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Self::Unread(None) => "unread items".to_string(),
+            Self::Unread(Some(tag)) => format!("unread items tagged #{tag}"),
+            Self::AllMy(_) => "items suggested by you".to_string(),
+            Self::Archive => "deleted items".to_string(),
+        }
+    }
+}
+
+/// Render page of the list – `items` should be sorted in the queue order,
+/// with items snoozed at the moment `now` at the end. Archived items should be
+/// sorted by the time of deletion, their snoozes aren't shown.
+///
+/// Returns message text, escaped for Telegram, and buttons for the items and pages.
+pub fn render_page(
+    view: &ListView,
+    items: &[(Key, ContentItem)],
+    page: usize,
+    now: OffsetDateTime,
+) -> (String, InlineKeyboardMarkup) {
+    if items.is_empty() {
+        let text = match view {
+            ListView::Archive => "The archive is empty",
+            _ => "You have no entries yet",
+        };
+        return (tg_escape(text), InlineKeyboardMarkup::default());
+    }
+
+    let (page, pages) = pages::clamp(items.len(), page, PAGE_SIZE);
+    let entries: Vec<_> = items
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(index, (key, item))| (index + 1, key, item))
+        .collect();
+
+    let snoozed_until = |item: &ContentItem| {
+        item.snoozed_until()
+            .filter(|_| *view != ListView::Archive && item.is_snoozed(now))
+    };
+    let snoozed = items
+        .iter()
+        .filter(|(_, item)| snoozed_until(item).is_some())
+        .count();
+    let mut text = format!("📋 {} {}", items.len(), view.describe());
+    if snoozed > 0 {
+        text.push_str(&format!(" ({snoozed} snoozed)"));
+    }
+    text.push_str(&format!(", page {}/{pages}:\n", page + 1));
+
+    let mut in_snoozed = false;
+    for (number, _, item) in &entries {
+        match snoozed_until(item) {
+            Some(until) => {
+                if !in_snoozed {
+                    text.push_str("\n\n⏰ Snoozed:\n");
+                    in_snoozed = true;
+                }
+                text.push_str(&format!(
+                    "\n{number}. {} – @{}, until {}",
+                    pages::shorten(item.content()),
+                    item.author(),
+                    until.date()
+                ));
+            }
+            None => text.push_str(&format!(
+                "\n{number}. {} – @{}",
+                pages::shorten(item.content()),
+                item.author()
+            )),
+        }
+        if let Some(archived_at) = item.archived_at().filter(|_| *view == ListView::Archive) {
+            text.push_str(&format!(", deleted {}", archived_at.date()));
+        }
+    }
+
+    let mut keyboard = vec![
+        entries
+            .iter()
+            .map(|(number, key, _)| Callback::show(key).as_button(format!("🔎 {number}")))
+            .collect(),
+        entries
+            .iter()
+            .map(|(number, key, _)| match view {
+                ListView::Archive => {
+                    Callback::list_restore(page, key).as_button(format!("♻️ {number}"))
+                }
+                _ => Callback::list_mark_as_read(view, page, key).as_button(format!("☑️ {number}")),
+            })
+            .collect(),
+    ];
+    let navigation = pages::navigation(page, pages, |page| Callback::list(view, page));
+    if !navigation.is_empty() {
+        keyboard.push(navigation);
+    }
+
+    (tg_escape(&text), InlineKeyboardMarkup::new(keyboard))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Duration;
+
+    use super::*;
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap()
+    }
+
+    #[test]
+    /// Test that views survive the trip through the button payload
+    fn test_payload_roundtrip() {
+        for view in [
+            ListView::Unread(None),
+            ListView::Unread(Some("horror".to_string())),
+            ListView::AllMy(UserId(123456789)),
+            ListView::Archive,
+        ] {
+            assert_eq!(ListView::from_payload(&view.to_payload()), Some(view));
+        }
+
+        assert_eq!(ListView::from_payload("my"), None);
+        assert_eq!(ListView::from_payload("read"), None);
+    }
+
+    #[test]
+    /// Test that `/unread` accepts a single tag of a limited length
+    fn test_parse_unread() {
+        assert_eq!(ListView::parse_unread(" "), Some(ListView::Unread(None)));
+        assert_eq!(
+            ListView::parse_unread("#Horror"),
+            Some(ListView::Unread(Some("horror".to_string())))
+        );
+        assert_eq!(
+            ListView::parse_unread("short"),
+            Some(ListView::Unread(Some("short".to_string())))
+        );
+
+        assert_eq!(ListView::parse_unread("#"), None);
+        assert_eq!(ListView::parse_unread("#horror #short"), None);
+        assert_eq!(ListView::parse_unread("#averyveryverylongtag"), None);
+
+        // the longest payload still fits
+        let view = ListView::parse_unread(&"x".repeat(MAX_TAG_LEN)).unwrap();
+        let callback = Callback::list_mark_as_read(&view, pages::MAX_PAGES - 1, &Key::generate());
+        assert!(callback.to_payload().len() <= 64);
+    }
+
+    #[test]
+    /// Test that every item on the page has its buttons and pages have navigation
+    fn test_render_page() {
+        let items: Vec<_> = (0..7)
+            .map(|i| {
+                (
+                    Key::generate(),
+                    ContentItem::legacy("alice", format!("item {i}")),
+                )
+            })
+            .collect();
+        let rows = |keyboard: InlineKeyboardMarkup| -> Vec<Vec<String>> {
+            keyboard
+                .inline_keyboard
+                .into_iter()
+                .map(|row| row.into_iter().map(|button| button.text).collect())
+                .collect()
+        };
+
+        let (text, keyboard) = render_page(&ListView::Unread(None), &items, 0, now());
+        assert!(text.contains("7 unread items, page 1/2"));
+        assert!(text.contains("item 4"));
+        assert!(!text.contains("item 5"));
+        assert_eq!(
+            rows(keyboard),
+            [
+                vec!["🔎 1", "🔎 2", "🔎 3", "🔎 4", "🔎 5"],
+                vec!["☑️ 1", "☑️ 2", "☑️ 3", "☑️ 4", "☑️ 5"],
+                vec!["Next ➡️"],
+            ]
+        );
+
+        let (text, keyboard) = render_page(&ListView::Unread(None), &items, 1, now());
+        assert!(text.contains("item 6"));
+        assert_eq!(
+            rows(keyboard),
+            [vec!["🔎 6", "🔎 7"], vec!["☑️ 6", "☑️ 7"], vec!["⬅️ Prev"]]
+        );
+
+        let (text, keyboard) = render_page(&ListView::Unread(None), &[], 0, now());
+        assert!(text.starts_with("You have no entries yet"));
+        assert!(rows(keyboard).is_empty());
+    }
+
+    #[test]
+    /// Test that snoozed items are listed under their own heading, expired snoozes aren't
+    fn test_render_snoozed() {
+        let mut snoozed = ContentItem::legacy("alice", "snoozed item");
+        snoozed.snooze(now() + Duration::days(1));
+        let mut expired = ContentItem::legacy("alice", "expired item");
+        expired.snooze(now() - Duration::days(1));
+        let items = [(Key::generate(), expired), (Key::generate(), snoozed)];
+
+        let (text, _) = render_page(&ListView::Unread(None), &items, 0, now());
+        assert!(text.contains("2 unread items \\(1 snoozed\\)"));
+
+        let (active, snoozed) = text.split_once("⏰ Snoozed:").unwrap();
+        assert!(active.contains("expired item"));
+        assert!(snoozed.contains("snoozed item – @alice, until 2023\\-11\\-15"));
+    }
+
+    #[test]
+    /// Test that the archive shows when items were deleted and has buttons to restore them
+    fn test_render_archive() {
+        let mut deleted = ContentItem::legacy("alice", "deleted item");
+        deleted.snooze(now() + Duration::days(1));
+        deleted.archive(now() - Duration::days(1));
+        let items = [(Key::generate(), deleted)];
+
+        let (text, keyboard) = render_page(&ListView::Archive, &items, 0, now());
+        assert!(text.contains("1 deleted items, page 1/1"));
+        assert!(text.contains("deleted item – @alice, deleted 2023\\-11\\-13"));
+        assert!(!text.contains("Snoozed"));
+        assert_eq!(keyboard.inline_keyboard[1][0].text, "♻️ 1");
+
+        let (text, _) = render_page(&ListView::Archive, &[], 0, now());
+        assert_eq!(text, "The archive is empty");
+    }
+}
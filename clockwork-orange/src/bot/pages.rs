@@ -0,0 +1,75 @@
+//! Helpers for messages showing items page by page, e.g. `/history` or `/unread`.
+
+use teloxide::types::InlineKeyboardButton;
+
+use super::callbacks::Callback;
+
+/// Items longer than this are shortened on the page
+const MAX_CONTENT_CHARS: usize = 100;
+
+/// Most pages a message can have – page numbers are kept in button payloads,
+/// which are limited to 64 bytes, so they can't be longer than 3 digits
+pub const MAX_PAGES: usize = 1000;
+
+/// Get number of pages and the page clamped to them, e.g. when items were removed meanwhile.
+///
+/// There is always at least one page, even an empty one, and at most `MAX_PAGES`,
+/// items past them aren't shown.
+pub fn clamp(items: usize, page: usize, page_size: usize) -> (usize, usize) {
+    let pages = items.div_ceil(page_size).clamp(1, MAX_PAGES);
+
+    (page.min(pages - 1), pages)
+}
+
+/// Prev/Next buttons for the page, empty if there is a single page
+pub fn navigation(
+    page: usize,
+    pages: usize,
+    callback: impl Fn(usize) -> Callback,
+) -> Vec<InlineKeyboardButton> {
+    let mut buttons = Vec::new();
+    if page > 0 {
+        buttons.push(callback(page - 1).as_button("⬅️ Prev"));
+    }
+    if page + 1 < pages {
+        buttons.push(callback(page + 1).as_button("Next ➡️"));
+    }
+
+    buttons
+}
+
+/// Shorten content to a single line of `MAX_CONTENT_CHARS`
+pub fn shorten(content: &str) -> String {
+    let line = content.lines().next().unwrap_or_default();
+    if line.chars().count() <= MAX_CONTENT_CHARS && line.len() == content.len() {
+        return line.to_string();
+    }
+
+    let mut short: String = line.chars().take(MAX_CONTENT_CHARS).collect();
+    short.push('…');
+
+    short
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that pages are clamped and there is always one
+    fn test_clamp() {
+        assert_eq!(clamp(0, 0, 10), (0, 1));
+        assert_eq!(clamp(10, 1, 10), (0, 1));
+        assert_eq!(clamp(11, 1, 10), (1, 2));
+        assert_eq!(clamp(25, 10, 10), (2, 3));
+        assert_eq!(clamp(usize::MAX, usize::MAX, 1), (MAX_PAGES - 1, MAX_PAGES));
+    }
+
+    #[test]
+    /// Test that long and multiline items are shortened
+    fn test_shorten() {
+        assert_eq!(shorten("https://example.com"), "https://example.com");
+        assert_eq!(shorten("title\ndescription"), "title…");
+        assert_eq!(shorten(&"a".repeat(150)), format!("{}…", "a".repeat(100)));
+    }
+}
@@ -0,0 +1,379 @@
+//! Sessions of `/random` – rerolling the item in place until something fits,
+//! and arguments of `/random` and `/random_settings`.
+//!
+//! Sessions are kept in memory, keyed by the message, and are forgotten after
+//! `SESSION_TTL` or on restart. A forgotten session is resumed from the item
+//! the message shows with the chat settings, so buttons of old messages keep working.
+//!
+//! Arguments are words separated by spaces, in any order:
+//! - `uniform`, `older` or `alternate` – weighting
+//! - `max-age=30d` – only items added at most 30 days ago, `off` disables
+//! - `no-repeat=7d` – skip items shown in the last 7 days, `off` disables
+//! - `me` or `@username` – only items of the author, `/random` only
+//! - `#tag` – only items with the tag or the category, `/random` only
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use teloxide::types::{ChatId, MessageId, UserId};
+
+use crate::{
+    content_item::normalize_tag,
+    settings::RandomSettings,
+    storage::{AuthorFilter, Key, RandomOptions, Weighting},
+};
+
+/// How long to remember what was shown in the session
+const SESSION_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Items shown in a single `/random` message
+#[derive(Debug, Clone)]
+pub struct Session {
+    started_at: Instant,
+    /// Options the session was started with
+    options: RandomOptions,
+    /// Item the message shows now
+    current: Key,
+    shown: HashSet<Key>,
+    /// Items which shouldn't be shown again in this session
+    snoozed: HashSet<Key>,
+}
+
+impl Session {
+    /// Start session with the item shown first
+    pub fn new(current: &Key, options: RandomOptions) -> Self {
+        Self {
+            started_at: Instant::now(),
+            options,
+            current: current.clone(),
+            shown: HashSet::from([current.clone()]),
+            snoozed: HashSet::new(),
+        }
+    }
+
+    /// Don't show the item again in this session
+    pub fn snooze(&mut self, key: &Key) {
+        self.snoozed.insert(key.clone());
+    }
+
+    /// Options to pick the next item with – one which wasn't shown yet or,
+    /// when starting over, any item but the current one. Snoozed items are never picked.
+    pub fn next_options(&self, start_over: bool) -> RandomOptions {
+        let exclude = if start_over {
+            HashSet::from([self.current.clone()])
+        } else {
+            self.shown.clone()
+        };
+
+        RandomOptions {
+            exclude: exclude.union(&self.snoozed).cloned().collect(),
+            ..self.options.clone()
+        }
+    }
+
+    /// Make the picked item the current one
+    pub fn show(&mut self, key: &Key, start_over: bool) {
+        if start_over {
+            self.shown.clear();
+        }
+        self.shown.insert(key.clone());
+        self.current = key.clone();
+    }
+}
+
+/// Sessions of all `/random` messages, shared between handlers
+#[derive(Debug, Clone, Default)]
+pub struct RandomSessions {
+    sessions: Arc<Mutex<HashMap<(ChatId, MessageId), Session>>>,
+}
+
+impl RandomSessions {
+    /// Take the session of the message out, `None` if it's forgotten
+    pub fn take(&self, chat_id: ChatId, message_id: MessageId) -> Option<Session> {
+        self.lock().remove(&(chat_id, message_id))
+    }
+
+    /// Store the session of the message, forgetting expired ones
+    pub fn put(&self, chat_id: ChatId, message_id: MessageId, session: Session) {
+        let mut sessions = self.lock();
+        sessions.retain(|_, session| session.started_at.elapsed() < SESSION_TTL);
+        sessions.insert((chat_id, message_id), session);
+    }
+
+    /// Forget the session of the message, e.g. when the item was chosen
+    pub fn finish(&self, chat_id: ChatId, message_id: MessageId) {
+        self.lock().remove(&(chat_id, message_id));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(ChatId, MessageId), Session>> {
+        // sessions are only a convenience, so state left by a panicked handler is fine
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Single argument of `/random` or `/random_settings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Arg {
+    Weighting(Weighting),
+    MaxAge(Option<u16>),
+    NoRepeat(Option<u16>),
+    Author(AuthorFilter),
+    Tag(String),
+}
+
+impl Arg {
+    fn parse(arg: &str, user_id: UserId, username: &str) -> Option<Self> {
+        if let Some(username) = arg.strip_prefix('@').filter(|name| !name.is_empty()) {
+            return Some(Self::Author(AuthorFilter::Username(username.to_string())));
+        }
+        if let Some(tag) = arg.strip_prefix('#').filter(|tag| !tag.is_empty()) {
+            return Some(Self::Tag(normalize_tag(tag)));
+        }
+
+        let arg = arg.to_lowercase();
+        match arg.as_str() {
+            "uniform" => return Some(Self::Weighting(Weighting::Uniform)),
+            "older" => return Some(Self::Weighting(Weighting::Older)),
+            "alternate" => return Some(Self::Weighting(Weighting::Alternate)),
+            "me" => {
+                return Some(Self::Author(AuthorFilter::User(
+                    user_id,
+                    username.to_string(),
+                )))
+            }
+            _ => {}
+        }
+
+        let (name, value) = arg.split_once('=')?;
+        let days = match value {
+            "off" => None,
+            value => Some(parse_days(value)?),
+        };
+
+        match name {
+            "max-age" => Some(Self::MaxAge(days)),
+            "no-repeat" => Some(Self::NoRepeat(days)),
+            _ => None,
+        }
+    }
+
+    fn apply(self, settings: &mut RandomSettings) {
+        match self {
+            Self::Weighting(weighting) => settings.weighting = weighting,
+            Self::MaxAge(days) => settings.max_age_days = days,
+            Self::NoRepeat(days) => settings.no_repeat_days = days,
+            Self::Author(_) | Self::Tag(_) => {}
+        }
+    }
+}
+
+/// Parse a positive number of days, like `30` or `30d`
+fn parse_days(value: &str) -> Option<u16> {
+    value
+        .strip_suffix('d')
+        .unwrap_or(value)
+        .parse()
+        .ok()
+        .filter(|days| *days > 0)
+}
+
+/// Parse arguments of `/random` on top of the chat settings.
+///
+/// Returns `None` if some argument is unknown.
+pub fn parse_options(
+    args: &str,
+    settings: &RandomSettings,
+    user_id: UserId,
+    username: &str,
+) -> Option<RandomOptions> {
+    let mut settings = settings.clone();
+    let (mut author, mut tag) = (None, None);
+
+    for arg in args.split_whitespace() {
+        match Arg::parse(arg, user_id, username)? {
+            Arg::Author(filter) => author = Some(filter),
+            Arg::Tag(filter) => tag = Some(filter),
+            arg => arg.apply(&mut settings),
+        }
+    }
+
+    Some(RandomOptions {
+        author,
+        tag,
+        ..settings.to_options()
+    })
+}
+
+/// Parse arguments of `/random_settings`, `reset` restores the defaults.
+///
+/// Returns `None` if some argument is unknown or is an author or tag filter,
+/// which only make sense for a single `/random`.
+pub fn parse_settings(args: &str, settings: &RandomSettings) -> Option<RandomSettings> {
+    let mut settings = settings.clone();
+
+    for arg in args.split_whitespace() {
+        if arg.eq_ignore_ascii_case("reset") {
+            settings = RandomSettings::default();
+            continue;
+        }
+
+        match Arg::parse(arg, UserId(0), "")? {
+            Arg::Author(_) | Arg::Tag(_) => return None,
+            arg => arg.apply(&mut settings),
+        }
+    }
+
+    Some(settings)
+}
+
+/// Describe the settings in a human-readable way
+pub fn describe_settings(settings: &RandomSettings) -> String {
+    let weighting = match settings.weighting {
+        Weighting::Uniform => "uniform – every item is equally likely",
+        Weighting::Older => "older – items waiting longer are more likely",
+        Weighting::Alternate => {
+            "alternate – items of somebody else than the last shown author go first"
+        }
+    };
+    let days =
+        |days: Option<u16>| days.map_or_else(|| "off".to_string(), |days| format!("{days}d"));
+
+    format!(
+        "Weighting: {weighting}\nMax age: {}\nNo repeat: {}",
+        days(settings.max_age_days),
+        days(settings.no_repeat_days),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that shown items are excluded until the session starts over
+    fn test_next_options_excludes_shown() {
+        let (first, second) = (Key::generate(), Key::generate());
+        let mut session = Session::new(&first, RandomOptions::default());
+
+        session.show(&second, false);
+        assert_eq!(
+            session.next_options(false).exclude,
+            HashSet::from([first.clone(), second.clone()])
+        );
+
+        // starts over, but doesn't repeat the current item
+        assert_eq!(
+            session.next_options(true).exclude,
+            HashSet::from([second.clone()])
+        );
+        session.show(&first, true);
+        assert_eq!(session.shown, HashSet::from([first.clone()]));
+    }
+
+    #[test]
+    /// Test that snoozed items are excluded, even when starting over,
+    /// and the options of the session are kept
+    fn test_next_options_excludes_snoozed() {
+        let (first, snoozed) = (Key::generate(), Key::generate());
+        let options = RandomOptions {
+            weighting: Weighting::Older,
+            ..Default::default()
+        };
+        let mut session = Session::new(&first, options);
+        session.snooze(&snoozed);
+
+        for start_over in [false, true] {
+            let options = session.next_options(start_over);
+            assert!(options.exclude.contains(&snoozed));
+            assert_eq!(options.weighting, Weighting::Older);
+        }
+    }
+
+    #[test]
+    /// Test that sessions are taken out once
+    fn test_take() {
+        let sessions = RandomSessions::default();
+        let (chat_id, message_id) = (ChatId(1), MessageId(1));
+        let (first, second) = (Key::generate(), Key::generate());
+
+        let mut session = Session::new(&first, RandomOptions::default());
+        session.snooze(&second);
+        sessions.put(chat_id, message_id, session);
+
+        let session = sessions.take(chat_id, message_id).unwrap();
+        assert!(session.snoozed.contains(&second));
+
+        // taken out, so it's forgotten now
+        assert!(sessions.take(chat_id, message_id).is_none());
+    }
+
+    #[test]
+    /// Test that `/random` arguments override the settings
+    fn test_parse_options() {
+        let settings = RandomSettings {
+            weighting: Weighting::Older,
+            max_age_days: Some(30),
+            ..Default::default()
+        };
+        let parse = |args| parse_options(args, &settings, UserId(1), "alice");
+
+        assert_eq!(parse(""), Some(settings.to_options()));
+        assert_eq!(
+            parse("alternate max-age=off no-repeat=7d me"),
+            Some(RandomOptions {
+                weighting: Weighting::Alternate,
+                no_repeat: Some(time::Duration::days(7)),
+                author: Some(AuthorFilter::User(UserId(1), "alice".to_string())),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse("@Bob").unwrap().author,
+            Some(AuthorFilter::Username("Bob".to_string()))
+        );
+        assert_eq!(
+            parse("MAX-AGE=7").unwrap().max_age,
+            Some(time::Duration::days(7))
+        );
+        assert_eq!(parse("#Short").unwrap().tag.as_deref(), Some("short"));
+
+        assert_eq!(parse("newer"), None);
+        assert_eq!(parse("max-age=0d"), None);
+        assert_eq!(parse("max-age=week"), None);
+        assert_eq!(parse("@"), None);
+        assert_eq!(parse("#"), None);
+    }
+
+    #[test]
+    /// Test that settings are updated and reset, but author and tag filters are rejected
+    fn test_parse_settings() {
+        let settings = RandomSettings {
+            weighting: Weighting::Older,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            parse_settings("no-repeat=14d", &settings),
+            Some(RandomSettings {
+                weighting: Weighting::Older,
+                no_repeat_days: Some(14),
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            parse_settings("reset max-age=30", &settings),
+            Some(RandomSettings {
+                max_age_days: Some(30),
+                ..Default::default()
+            })
+        );
+        assert_eq!(parse_settings("me", &settings), None);
+        assert_eq!(parse_settings("@alice", &settings), None);
+        assert_eq!(parse_settings("#short", &settings), None);
+    }
+}
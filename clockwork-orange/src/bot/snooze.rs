@@ -0,0 +1,180 @@
+//! Snoozing items – `/random` skips them until the snooze expires,
+//! `/unread` lists them separately.
+//!
+//! Items are snoozed with the ⏰ buttons, or with `/snooze` sent in reply
+//! to the item message: `/snooze 10d`, `/snooze 2w` or `/snooze 2023-12-31`.
+
+use teloxide::types::{InlineKeyboardButtonKind, InlineKeyboardMarkup, Message};
+use time::{Date, Duration, Month, OffsetDateTime};
+
+use crate::storage::Key;
+
+use super::callbacks::Callback;
+
+/// Days the ⏰ button of `/random` snoozes the item for
+pub const RANDOM_SNOOZE_DAYS: u16 = 7;
+
+/// Snooze periods offered by the menu, in days
+const CHOICES: [(u16, &str); 3] = [(1, "1 day"), (7, "1 week"), (30, "1 month")];
+
+/// Parse when the snooze should end: days (`10` or `10d`), weeks (`2w`)
+/// or a date (`2023-12-31`, the snooze ends at its start, in UTC).
+///
+/// Returns `None` for unknown formats and moments which aren't in the future.
+pub fn parse_until(args: &str, now: OffsetDateTime) -> Option<OffsetDateTime> {
+    let args = args.trim().to_lowercase();
+
+    let until = if let Some(weeks) = args.strip_suffix('w') {
+        now.checked_add(Duration::weeks(weeks.parse::<u16>().ok()?.into()))?
+    } else if let Ok(days) = args.strip_suffix('d').unwrap_or(&args).parse::<u16>() {
+        now.checked_add(Duration::days(days.into()))?
+    } else {
+        let mut parts = args.splitn(3, '-');
+        let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+        let month = Month::try_from(month.parse::<u8>().ok()?).ok()?;
+
+        Date::from_calendar_date(year.parse().ok()?, month, day.parse().ok()?)
+            .ok()?
+            .midnight()
+            .assume_utc()
+    };
+
+    (until > now).then_some(until)
+}
+
+/// When the snooze for the number of days started now ends, at most at the latest supported date
+pub fn days_from(now: OffsetDateTime, days: u16) -> OffsetDateTime {
+    now.saturating_add(Duration::days(days.into()))
+}
+
+/// Buttons to choose how long to snooze the item for, replacing the item buttons
+pub fn menu_keyboard(key: &Key, is_snoozed: bool) -> InlineKeyboardMarkup {
+    let choices = CHOICES
+        .iter()
+        .map(|(days, text)| Callback::snooze(key, *days).as_button(*text))
+        .collect();
+
+    let mut controls = Vec::new();
+    if is_snoozed {
+        controls.push(Callback::unsnooze(key).as_button("🔔 Wake up"));
+    }
+    controls.push(Callback::snooze_cancel(key).as_button("Cancel"));
+
+    InlineKeyboardMarkup::new(vec![choices, controls])
+}
+
+/// Find the item a message is about – by the payload of its buttons.
+///
+/// Used for commands sent in reply to the item message. Only messages about
+/// a single item count, lists have buttons for several items and give `None`.
+pub fn item_key(message: &Message) -> Option<Key> {
+    keyboard_item_key(message.reply_markup()?)
+}
+
+/// Find the item by the buttons, see `item_key`
+fn keyboard_item_key(keyboard: &InlineKeyboardMarkup) -> Option<Key> {
+    keyboard
+        .inline_keyboard
+        .iter()
+        .flatten()
+        .find_map(|button| match &button.kind {
+            InlineKeyboardButtonKind::CallbackData(payload) => {
+                Callback::from_payload(payload)?.key().cloned()
+            }
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bot::list::{self, ListView},
+        content_item::ContentItem,
+    };
+
+    fn date(year: i32, month: Month, day: u8, hour: u8) -> OffsetDateTime {
+        Date::from_calendar_date(year, month, day)
+            .unwrap()
+            .with_hms(hour, 0, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    #[test]
+    /// Test that days, weeks and dates are parsed, but only in the future
+    fn test_parse_until() {
+        let now = date(2023, Month::October, 7, 12);
+
+        assert_eq!(
+            parse_until("10", now),
+            Some(date(2023, Month::October, 17, 12))
+        );
+        assert_eq!(
+            parse_until(" 10D ", now),
+            Some(date(2023, Month::October, 17, 12))
+        );
+        assert_eq!(
+            parse_until("2w", now),
+            Some(date(2023, Month::October, 21, 12))
+        );
+        assert_eq!(
+            parse_until("2023-12-31", now),
+            Some(date(2023, Month::December, 31, 0))
+        );
+
+        assert_eq!(parse_until("", now), None);
+        assert_eq!(parse_until("0d", now), None);
+        assert_eq!(parse_until("2023-10-07", now), None);
+        assert_eq!(parse_until("tomorrow", now), None);
+        assert_eq!(parse_until("2023-13-01", now), None);
+
+        // too far in the future
+        assert_eq!(parse_until("1000000w", now), None);
+        assert_eq!(
+            parse_until("65535w", date(9999, Month::January, 1, 0)),
+            None
+        );
+        assert_eq!(parse_until("-1w", now), None);
+    }
+
+    #[test]
+    /// Test that items are found by buttons of single-item messages, but not of lists
+    fn test_keyboard_item_key() {
+        let key = Key::generate();
+        assert_eq!(
+            keyboard_item_key(&menu_keyboard(&key, false)),
+            Some(key.clone())
+        );
+
+        let now = date(2023, Month::October, 7, 12);
+        let items = [(key, ContentItem::legacy("alice", "https://example.com"))];
+        for view in [ListView::Unread(None), ListView::Archive] {
+            let (_, keyboard) = list::render_page(&view, &items, 0, now);
+            assert_eq!(keyboard_item_key(&keyboard), None);
+        }
+    }
+
+    #[test]
+    /// Test that the menu offers to wake up only snoozed items
+    fn test_menu_keyboard() {
+        let key = Key::generate();
+        let texts = |keyboard: InlineKeyboardMarkup| -> Vec<String> {
+            keyboard
+                .inline_keyboard
+                .into_iter()
+                .flatten()
+                .map(|button| button.text)
+                .collect()
+        };
+
+        assert_eq!(
+            texts(menu_keyboard(&key, false)),
+            ["1 day", "1 week", "1 month", "Cancel"]
+        );
+        assert_eq!(
+            texts(menu_keyboard(&key, true)),
+            ["1 day", "1 week", "1 month", "🔔 Wake up", "Cancel"]
+        );
+    }
+}
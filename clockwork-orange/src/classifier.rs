@@ -0,0 +1,493 @@
+//! Classification of links by their source – category of the item and canonical URL.
+//!
+//! Rules match the host of the link (subdomains included, `www.`, `m.` and `mobile.` ignored)
+//! and a regex over its path and query, like `/watch?v=...`. The first matching rule
+//! wins, rules from the file in `CLASSIFIER_RULES_PATH` go before the built-in ones,
+//! so operators can add sources or override the defaults. The file is a JSON array:
+//!
+//! ```json
+//! [{"hosts": ["vimeo.com"], "pattern": "^/(\\d+)", "category": "video", "canonical": "https://vimeo.com/$1"}]
+//! ```
+
+use std::{fs, path::Path, sync::Arc};
+
+use color_eyre::{eyre::WrapErr, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{content_item::Category, links};
+
+/// Rule as it's written in the config
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rule {
+    /// Hosts the rule applies to, any host if empty
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// Regex for the path and query, any link of the hosts matches if it's missing
+    #[serde(default)]
+    pub pattern: Option<String>,
+    pub category: Category,
+    /// Canonical URL, `$1` or `${name}` are replaced with groups of the pattern.
+    /// The link is kept as it is if it's missing
+    #[serde(default)]
+    pub canonical: Option<String>,
+}
+
+impl Rule {
+    fn new(hosts: &[&str], pattern: &str, category: Category, canonical: Option<&str>) -> Self {
+        Self {
+            hosts: hosts.iter().map(|host| host.to_string()).collect(),
+            pattern: Some(pattern.to_string()),
+            category,
+            canonical: canonical.map(str::to_string),
+        }
+    }
+}
+
+/// Result of the classification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Classification {
+    pub category: Category,
+    pub canonical: Option<Url>,
+}
+
+#[derive(Debug)]
+struct CompiledRule {
+    rule: Rule,
+    pattern: Option<Regex>,
+}
+
+/// Rules to classify links with, cheap to clone
+#[derive(Debug, Clone)]
+pub struct Classifier {
+    rules: Arc<Vec<CompiledRule>>,
+}
+
+impl Classifier {
+    /// Classifier with the rules from the file, if any, and the built-in rules after them
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let mut rules = match path {
+            Some(path) => {
+                let file = fs::read_to_string(path)
+                    .wrap_err_with(|| format!("failed to read classifier rules from {path:?}"))?;
+                serde_json::from_str(&file).wrap_err("failed to parse classifier rules")?
+            }
+            None => Vec::new(),
+        };
+        rules.extend(default_rules());
+
+        Self::new(rules)
+    }
+
+    pub fn new(rules: Vec<Rule>) -> Result<Self> {
+        let rules = rules
+            .into_iter()
+            .map(|rule| {
+                let pattern = rule
+                    .pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .wrap_err_with(|| format!("invalid pattern in classifier rule {rule:?}"))?;
+
+                Ok(CompiledRule { rule, pattern })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            rules: Arc::new(rules),
+        })
+    }
+
+    /// Classify the link by the first matching rule
+    pub fn classify(&self, url: &Url) -> Option<Classification> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return None;
+        }
+        let host = links::host(url)?;
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+
+        self.rules
+            .iter()
+            .find_map(|CompiledRule { rule, pattern }| {
+                let host_matches = rule.hosts.is_empty()
+                    || rule.hosts.iter().any(|rule_host| {
+                        host == rule_host
+                            || host
+                                .strip_suffix(rule_host.as_str())
+                                .is_some_and(|subdomain| subdomain.ends_with('.'))
+                    });
+                if !host_matches {
+                    return None;
+                }
+
+                let captures = match pattern {
+                    Some(pattern) => Some(pattern.captures(&path)?),
+                    None => None,
+                };
+                let canonical = rule.canonical.as_deref().and_then(|template| {
+                    let mut canonical = String::new();
+                    match &captures {
+                        Some(captures) => captures.expand(template, &mut canonical),
+                        None => canonical.push_str(template),
+                    }
+
+                    Url::parse(&canonical).ok()
+                });
+
+                Some(Classification {
+                    category: rule.category,
+                    canonical,
+                })
+            })
+    }
+
+    /// Classify the first link of the text, replacing it with the canonical URL.
+    ///
+    /// Returns the updated text and the category, if the link is known.
+    pub fn apply(&self, text: &str) -> (String, Option<Category>) {
+        let link = links::find(text).find_map(|(word, url)| Some((word, self.classify(&url)?)));
+
+        match link {
+            Some((
+                word,
+                Classification {
+                    category,
+                    canonical,
+                },
+            )) => {
+                let text = match canonical {
+                    Some(canonical) => text.replacen(word, canonical.as_str(), 1),
+                    None => text.to_string(),
+                };
+
+                (text, Some(category))
+            }
+            None => (text.to_string(), None),
+        }
+    }
+
+    /// Replace links with their canonical URLs.
+    ///
+    /// Returns the category of the first known link, if any.
+    pub fn apply_links(&self, links: Vec<Url>) -> (Vec<Url>, Option<Category>) {
+        let mut category = None;
+        let links = links
+            .into_iter()
+            .map(|link| match self.classify(&link) {
+                Some(classification) => {
+                    category = category.or(Some(classification.category));
+                    classification.canonical.unwrap_or(link)
+                }
+                None => link,
+            })
+            .collect();
+
+        (links, category)
+    }
+
+    /// Link in the form to compare it by – canonical URL of a known source
+    /// or just normalized link, so old items and shared variants of a link match new ones
+    pub fn canonical_link(&self, url: Url) -> Url {
+        let canonical = self
+            .classify(&url)
+            .and_then(|classification| classification.canonical)
+            .unwrap_or(url);
+
+        links::normalize(&canonical)
+    }
+
+    /// Links of the text in the form to compare them by, see `canonical_link`
+    pub fn canonical_links(&self, text: &str) -> Vec<Url> {
+        links::find(text)
+            .map(|(_, url)| self.canonical_link(url))
+            .collect()
+    }
+}
+
+/// Rules for well-known sources
+fn default_rules() -> Vec<Rule> {
+    use Category::*;
+
+    vec![
+        Rule::new(
+            &["youtube.com"],
+            r"^/watch\?(?:.*&)?v=([\w-]{11})",
+            Video,
+            Some("https://www.youtube.com/watch?v=$1"),
+        ),
+        Rule::new(
+            &["youtube.com"],
+            r"^/(?:shorts|live)/([\w-]{11})",
+            Video,
+            Some("https://www.youtube.com/watch?v=$1"),
+        ),
+        Rule::new(
+            &["youtu.be"],
+            r"^/([\w-]{11})",
+            Video,
+            Some("https://www.youtube.com/watch?v=$1"),
+        ),
+        Rule::new(
+            &["imdb.com"],
+            r"^/title/(tt\d+)",
+            Movie,
+            Some("https://www.imdb.com/title/$1/"),
+        ),
+        Rule::new(
+            &["kinopoisk.ru"],
+            r"^/film/(\d+)",
+            Movie,
+            Some("https://www.kinopoisk.ru/film/$1/"),
+        ),
+        Rule::new(
+            &["kinopoisk.ru"],
+            r"^/series/(\d+)",
+            Series,
+            Some("https://www.kinopoisk.ru/series/$1/"),
+        ),
+        Rule::new(
+            &["letterboxd.com"],
+            r"^/film/([\w-]+)",
+            Movie,
+            Some("https://letterboxd.com/film/$1/"),
+        ),
+        Rule::new(
+            &["goodreads.com"],
+            r"^/book/show/(\d+)",
+            Book,
+            Some("https://www.goodreads.com/book/show/$1"),
+        ),
+        Rule::new(
+            &["open.spotify.com"],
+            r"^/(?:intl-[\w-]+/)?(episode|show)/(\w+)",
+            Podcast,
+            Some("https://open.spotify.com/$1/$2"),
+        ),
+        Rule::new(
+            &["arxiv.org"],
+            r"^/(?:abs|pdf)/(\d{4}\.\d{4,5})",
+            Article,
+            Some("https://arxiv.org/abs/$1"),
+        ),
+        Rule::new(
+            &["medium.com", "substack.com", "habr.com"],
+            r"^/.+",
+            Article,
+            None,
+        ),
+        // blog posts and news on any site
+        Rule::new(
+            &[],
+            r"^/(?:.+/)?(?:\d{4}/\d{2}/|blog/|articles?/|posts?/|news/).+",
+            Article,
+            None,
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classify(classifier: &Classifier, url: &str) -> Option<(Category, Option<String>)> {
+        classifier
+            .classify(&Url::parse(url).unwrap())
+            .map(|classification| {
+                (
+                    classification.category,
+                    classification.canonical.map(String::from),
+                )
+            })
+    }
+
+    #[test]
+    /// Test that well-known sources get categories and canonical URLs
+    fn test_default_rules() {
+        let classifier = Classifier::new(default_rules()).unwrap();
+        let cases = [
+            (
+                "https://m.youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42",
+                Category::Video,
+                Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ),
+            (
+                "https://youtu.be/dQw4w9WgXcQ?si=tracking",
+                Category::Video,
+                Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ),
+            (
+                "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+                Category::Video,
+                Some("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+            ),
+            (
+                "https://m.imdb.com/title/tt0066921/?ref_=nv_sr_1",
+                Category::Movie,
+                Some("https://www.imdb.com/title/tt0066921/"),
+            ),
+            (
+                "https://www.kinopoisk.ru/film/5492/",
+                Category::Movie,
+                Some("https://www.kinopoisk.ru/film/5492/"),
+            ),
+            (
+                "https://www.kinopoisk.ru/series/464963/",
+                Category::Series,
+                Some("https://www.kinopoisk.ru/series/464963/"),
+            ),
+            (
+                "https://letterboxd.com/film/a-clockwork-orange/",
+                Category::Movie,
+                Some("https://letterboxd.com/film/a-clockwork-orange/"),
+            ),
+            (
+                "https://www.goodreads.com/book/show/41817486-a-clockwork-orange",
+                Category::Book,
+                Some("https://www.goodreads.com/book/show/41817486"),
+            ),
+            (
+                "https://open.spotify.com/intl-de/episode/4rOoJ6Egrf8K2IrywzwOMk?si=abc",
+                Category::Podcast,
+                Some("https://open.spotify.com/episode/4rOoJ6Egrf8K2IrywzwOMk"),
+            ),
+            (
+                "https://arxiv.org/pdf/1706.03762v7.pdf",
+                Category::Article,
+                Some("https://arxiv.org/abs/1706.03762"),
+            ),
+            ("https://habr.com/ru/articles/1/", Category::Article, None),
+            (
+                "https://example.com/2023/10/some-post",
+                Category::Article,
+                None,
+            ),
+        ];
+
+        for (url, category, canonical) in cases {
+            assert_eq!(
+                classify(&classifier, url),
+                Some((category, canonical.map(str::to_string))),
+                "{url}"
+            );
+        }
+
+        assert_eq!(classify(&classifier, "https://example.com/"), None);
+        assert_eq!(
+            classify(&classifier, "https://notyoutube.com/watch?v=dQw4w9WgXcQ"),
+            None
+        );
+        assert_eq!(
+            classify(&classifier, "https://www.youtube.com/feed/trending"),
+            None
+        );
+        assert_eq!(
+            classify(&classifier, "ftp://arxiv.org/abs/1706.03762"),
+            None
+        );
+    }
+
+    #[test]
+    /// Test that operator rules go before the built-in ones
+    fn test_operator_rules() {
+        let rules: Vec<Rule> = serde_json::from_str(
+            r#"[
+                {"hosts": ["vimeo.com"], "pattern": "^/(\\d+)", "category": "video",
+                 "canonical": "https://vimeo.com/$1"},
+                {"hosts": ["imdb.com"], "category": "series"}
+            ]"#,
+        )
+        .unwrap();
+        let mut all = rules;
+        all.extend(default_rules());
+        let classifier = Classifier::new(all).unwrap();
+
+        assert_eq!(
+            classify(&classifier, "https://player.vimeo.com/76979871?share=copy"),
+            Some((
+                Category::Video,
+                Some("https://vimeo.com/76979871".to_string())
+            ))
+        );
+        assert_eq!(
+            classify(&classifier, "https://www.imdb.com/title/tt0108778/"),
+            Some((Category::Series, None))
+        );
+    }
+
+    #[test]
+    /// Test that invalid patterns are reported
+    fn test_invalid_pattern() {
+        let rule = Rule::new(&[], "(", Category::Video, None);
+
+        assert!(Classifier::new(vec![rule]).is_err());
+    }
+
+    #[test]
+    /// Test that shared variants of links of known sources have the same canonical links
+    fn test_canonical_links() {
+        let classifier = Classifier::new(default_rules()).unwrap();
+        let links = |text| -> Vec<String> {
+            classifier
+                .canonical_links(text)
+                .into_iter()
+                .map(String::from)
+                .collect()
+        };
+
+        assert_eq!(
+            links("https://m.imdb.com/title/tt0066921/?ref_=nv_sr_1 and https://example.com/?utm_source=x"),
+            ["https://imdb.com/title/tt0066921", "https://example.com/"]
+        );
+        assert_eq!(
+            links("https://www.imdb.com/title/tt0066921/reviews"),
+            links("Watch it! https://imdb.com/title/tt0066921")
+        );
+    }
+
+    #[test]
+    /// Test that links get canonical URLs and the category of the first known one
+    fn test_apply_links() {
+        let classifier = Classifier::new(default_rules()).unwrap();
+        let links = [
+            "https://example.com/",
+            "https://m.imdb.com/title/tt0066921/?ref_=share",
+            "https://youtu.be/dQw4w9WgXcQ",
+        ]
+        .map(|link| Url::parse(link).unwrap())
+        .to_vec();
+
+        let (links, category) = classifier.apply_links(links);
+        assert_eq!(
+            links.iter().map(Url::as_str).collect::<Vec<_>>(),
+            [
+                "https://example.com/",
+                "https://www.imdb.com/title/tt0066921/",
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+            ]
+        );
+        assert_eq!(category, Some(Category::Movie));
+    }
+
+    #[test]
+    /// Test that the first known link of the text is replaced with the canonical URL
+    fn test_apply() {
+        let classifier = Classifier::new(default_rules()).unwrap();
+
+        assert_eq!(
+            classifier.apply("Watch this: https://youtu.be/dQw4w9WgXcQ?si=x, so good"),
+            (
+                "Watch this: https://www.youtube.com/watch?v=dQw4w9WgXcQ, so good".to_string(),
+                Some(Category::Video)
+            )
+        );
+        assert_eq!(
+            classifier.apply("just some text"),
+            ("just some text".to_string(), None)
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Command line interface.
+//!
+//! Without a subcommand the bot is started, other subcommands are
+//! maintenance tools for operators, working on the configured storage.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use teloxide::types::ChatId;
+
+use crate::{
+    config::StorageKind,
+    export::{self, Format},
+    storage::{
+        ListId, PostgresStorage, RedisStorage, RedisTimeouts, SqliteStorage, StorageBackend,
+    },
+    transfer::transfer_list,
+};
+
+/// Bot that works like "to-watch" ToDo list in Telegram
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Subcommand>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Subcommand {
+    /// Start the bot, the default
+    Run,
+    /// Export items, read ones included, from the storage
+    Export {
+        /// Dump format: json or csv
+        #[arg(long, default_value_t)]
+        format: Format,
+        /// Export only the list of this chat, all lists by default
+        #[arg(long, allow_negative_numbers = true)]
+        list: Option<i64>,
+        /// File to write to, stdout by default
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Import items to the storage, overwriting ones with the same keys
+    Import {
+        /// Dump format: json or csv
+        #[arg(long, default_value_t)]
+        format: Format,
+        /// File written by `export`
+        input: PathBuf,
+    },
+    /// Copy all items from the storage to another one, keeping their keys
+    Migrate {
+        #[command(flatten)]
+        target: Target,
+        /// Only report what would be copied, without writing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Storage to migrate items to
+#[derive(Debug, clap::Args)]
+pub struct Target {
+    /// Kind of the target storage
+    #[arg(long = "to", value_enum)]
+    kind: StorageKind,
+    /// Redis URL of the target storage
+    #[arg(long, required_if_eq("kind", "redis"))]
+    redis_url: Option<String>,
+    /// Path to the SQLite database of the target storage, created if missing
+    #[arg(long, required_if_eq("kind", "sqlite"))]
+    sqlite_path: Option<PathBuf>,
+    /// Postgres URL of the target storage
+    #[arg(long, required_if_eq("kind", "postgres"))]
+    database_url: Option<String>,
+}
+
+/// Export items of the storage to file or stdout
+pub async fn export<B: StorageBackend>(
+    storage: &B,
+    format: Format,
+    list: Option<i64>,
+    output: Option<&Path>,
+) -> Result<()> {
+    let lists = match list {
+        Some(list) => vec![ListId::from(ChatId(list))],
+        None => storage.get_lists().await?,
+    };
+    let records = export::export(storage, &lists).await?;
+
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => {
+            Box::new(BufWriter::new(File::create(path).wrap_err_with(|| {
+                format!("failed to create {}", path.display())
+            })?))
+        }
+        None => Box::new(io::stdout().lock()),
+    };
+    export::write_records(&records, format, &mut writer)?;
+    writer.flush().wrap_err("failed to flush the dump")?;
+
+    Ok(())
+}
+
+/// Import items from the file to the storage
+pub async fn import<B: StorageBackend>(
+    storage: &mut B,
+    format: Format,
+    input: &Path,
+) -> Result<()> {
+    let file = File::open(input).wrap_err_with(|| format!("failed to open {}", input.display()))?;
+    let records = export::read_records(format, BufReader::new(file))?;
+
+    export::import(storage, records).await?;
+
+    Ok(())
+}
+
+/// Copy all items of the storage to the target one.
+///
+/// Redis target uses the same timeouts as the configured storage.
+pub async fn migrate<B: StorageBackend>(
+    source: &B,
+    target: &Target,
+    redis_timeouts: RedisTimeouts,
+    dry_run: bool,
+) -> Result<()> {
+    // arguments required for the kind are checked by clap
+    match target.kind {
+        StorageKind::InMemory => bail!("in-memory storage is lost on exit, can't migrate to it"),
+        StorageKind::Redis => {
+            let url = target.redis_url.as_deref().unwrap_or_default();
+            let mut target = RedisStorage::new(url, redis_timeouts).await?;
+
+            copy_lists(source, &mut target, dry_run).await
+        }
+        StorageKind::Sqlite => {
+            let path = target.sqlite_path.as_deref().unwrap_or(Path::new(""));
+            let mut target = SqliteStorage::new(path).await?;
+
+            copy_lists(source, &mut target, dry_run).await
+        }
+        StorageKind::Postgres => {
+            let url = target.database_url.as_deref().unwrap_or_default();
+            let mut target = PostgresStorage::new(url).await?;
+
+            copy_lists(source, &mut target, dry_run).await
+        }
+    }
+}
+
+/// Copy lists one by one, reporting progress to stderr
+async fn copy_lists<S, T>(source: &S, target: &mut T, dry_run: bool) -> Result<()>
+where
+    S: StorageBackend,
+    T: StorageBackend,
+{
+    let lists = source.get_lists().await?;
+    let total = lists.len();
+    eprintln!("Found {total} lists in the source storage");
+
+    let (mut items, mut copied, mut incomplete) = (0, 0, Vec::new());
+    for (index, list) in lists.iter().enumerate() {
+        let report = transfer_list(source, target, list, dry_run).await?;
+        items += report.items;
+        copied += report.copied;
+
+        if dry_run {
+            eprintln!(
+                "[{}/{total}] list {list}: {} items ({} read) would be copied, \
+                {} of them already exist in the target",
+                index + 1,
+                report.items,
+                report.read,
+                report.existing,
+            );
+            continue;
+        }
+
+        eprintln!(
+            "[{}/{total}] list {list}: copied {} of {} items ({} read), verified {}",
+            index + 1,
+            report.copied,
+            report.items,
+            report.read,
+            report.verified,
+        );
+        if !report.is_complete() {
+            incomplete.push(*list);
+        }
+    }
+
+    if dry_run {
+        eprintln!("Dry run: {items} items in {total} lists would be copied");
+        return Ok(());
+    }
+
+    eprintln!("Copied {copied} of {items} items in {total} lists");
+    if !incomplete.is_empty() {
+        bail!("items of lists {incomplete:?} don't match after copying, check the target storage");
+    }
+
+    Ok(())
+}
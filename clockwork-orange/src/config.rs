@@ -1,16 +1,21 @@
-use std::{net::SocketAddr, ops::Deref, sync::Arc};
+use std::{net::SocketAddr, ops::Deref, path::PathBuf, sync::Arc, time::Duration};
 
 use color_eyre::Result;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use crate::storage::RedisTimeouts;
+
 #[non_exhaustive]
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, clap::ValueEnum)]
 #[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
 /// Available storage types
 pub enum StorageKind {
     InMemory,
     Redis,
+    Sqlite,
+    Postgres,
 }
 
 #[non_exhaustive]
@@ -27,8 +32,16 @@ pub struct ConfigInner {
     pub storage: StorageKind,
     pub bot_mode: BotMode,
     pub redis_url: Option<String>,
+    pub redis_connect_timeout_ms: Option<u64>,
+    pub redis_response_timeout_ms: Option<u64>,
+    /// Chat to move items stored before lists were introduced to
+    pub redis_legacy_list_id: Option<i64>,
+    pub sqlite_path: Option<PathBuf>,
+    pub database_url: Option<String>,
     pub bind_to: SocketAddr,
     pub webhook_url: Url,
+    /// JSON file with extra rules to classify links by, see `classifier`
+    pub classifier_rules_path: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -38,6 +51,19 @@ impl Config {
     pub fn from_env() -> Result<Self> {
         Ok(Self(Arc::new(envy::from_env()?)))
     }
+
+    /// Redis timeouts, defaults overridden by the configured ones
+    pub fn redis_timeouts(&self) -> RedisTimeouts {
+        let mut timeouts = RedisTimeouts::default();
+        if let Some(connect_timeout) = self.redis_connect_timeout_ms {
+            timeouts.connect = Duration::from_millis(connect_timeout);
+        }
+        if let Some(response_timeout) = self.redis_response_timeout_ms {
+            timeouts.response = Duration::from_millis(response_timeout);
+        }
+
+        timeouts
+    }
 }
 
 impl Deref for Config {
@@ -1,35 +1,240 @@
-use std::borrow::Borrow;
+use std::{borrow::Borrow, fmt, str::FromStr};
 
+use color_eyre::{eyre::eyre, Report, Result};
 use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, MessageId, UserId};
 use time::OffsetDateTime;
+use url::Url;
+
+/// What kind of content the item is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Movie,
+    Series,
+    Book,
+    Article,
+    Video,
+    Podcast,
+}
+
+impl Category {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Movie => "movie",
+            Self::Series => "series",
+            Self::Book => "book",
+            Self::Article => "article",
+            Self::Video => "video",
+            Self::Podcast => "podcast",
+        }
+    }
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Self::Movie => "🎬",
+            Self::Series => "📺",
+            Self::Book => "📚",
+            Self::Article => "📰",
+            Self::Video => "▶️",
+            Self::Podcast => "🎧",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Category {
+    type Err = Report;
+
+    /// Parse category name, case-insensitively, a few synonyms are accepted too
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "movie" | "film" => Ok(Self::Movie),
+            "series" | "show" => Ok(Self::Series),
+            "book" => Ok(Self::Book),
+            "article" => Ok(Self::Article),
+            "video" => Ok(Self::Video),
+            "podcast" => Ok(Self::Podcast),
+            _ => Err(eyre!("unknown category `{s}`")),
+        }
+    }
+}
+
+/// Kind of the file attached to the item
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    Photo,
+    Video,
+    Document,
+}
+
+impl AttachmentKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Photo => "photo",
+            Self::Video => "video",
+            Self::Document => "document",
+        }
+    }
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Self::Photo => "🖼",
+            Self::Video => "🎞",
+            Self::Document => "📎",
+        }
+    }
+}
+
+impl FromStr for AttachmentKind {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "photo" => Ok(Self::Photo),
+            "video" => Ok(Self::Video),
+            "document" => Ok(Self::Document),
+            _ => Err(eyre!("unknown attachment kind `{s}`")),
+        }
+    }
+}
+
+/// File sent along with the item – Telegram keeps it, we keep its id
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    pub file_id: String,
+}
+
+/// Message the item was added from, in the chat of the list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Source {
+    pub chat_id: ChatId,
+    pub message_id: MessageId,
+}
+
+/// Where the forwarded message comes from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ForwardOrigin {
+    /// Original author – username, name of the user, or title of the channel with the signature
+    pub author: String,
+    /// Channel or group the message was posted to on behalf of the chat
+    pub chat_id: Option<ChatId>,
+    /// Id of the original post in the channel
+    pub message_id: Option<MessageId>,
+}
+
+/// Bring tag to the stored form – lowercase, without the leading `#`
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim_start_matches('#').to_lowercase()
+}
 
 #[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
 pub struct ContentItem {
-    /// Author of an item
+    /// Telegram id of the author, `None` for items added before ids were recorded
+    author_id: Option<UserId>,
+    /// Author's username at the moment the item was added
     author: String,
     /// URL of an item
     content: String,
+    /// When the item was added – in UTC, `None` for items added before it was recorded
+    added_at: Option<OffsetDateTime>,
     /// Whether the user has "read" the content item and when – in UTC
     read_at: Option<OffsetDateTime>,
+    /// Whether the item was deleted to the archive and when – in UTC
+    archived_at: Option<OffsetDateTime>,
+    /// When `/random` showed the item last time – in UTC
+    shown_at: Option<OffsetDateTime>,
+    /// Until when the item is snoozed – `/random` skips it till then, in UTC
+    snoozed_until: Option<OffsetDateTime>,
+    category: Option<Category>,
+    /// Normalized tags, sorted and without duplicates, see `normalize_tag`
+    tags: Vec<String>,
+    /// Links of the message, including ones hidden behind the text, without duplicates
+    links: Vec<Url>,
+    attachment: Option<Attachment>,
+    /// `None` for items added before sources were recorded, and for imported ones
+    source: Option<Source>,
+    forward_origin: Option<ForwardOrigin>,
 }
 
 impl ContentItem {
     /// Constructor method, newly created ContentItem presumes to be unread
-    pub fn new(author: impl Borrow<str>, content: impl Borrow<str>) -> Self {
+    pub fn new(
+        author_id: UserId,
+        author: impl Borrow<str>,
+        content: impl Borrow<str>,
+        added_at: OffsetDateTime,
+    ) -> Self {
         Self {
+            author_id: Some(author_id),
             author: author.borrow().to_string(),
             content: content.borrow().to_string(),
+            added_at: Some(added_at),
             read_at: None,
+            archived_at: None,
+            shown_at: None,
+            snoozed_until: None,
+            category: None,
+            tags: Vec::new(),
+            links: Vec::new(),
+            attachment: None,
+            source: None,
+            forward_origin: None,
+        }
+    }
+
+    /// Constructor for items stored before author ids and creation time were recorded
+    pub fn legacy(author: impl Borrow<str>, content: impl Borrow<str>) -> Self {
+        Self {
+            author_id: None,
+            author: author.borrow().to_string(),
+            content: content.borrow().to_string(),
+            added_at: None,
+            read_at: None,
+            archived_at: None,
+            shown_at: None,
+            snoozed_until: None,
+            category: None,
+            tags: Vec::new(),
+            links: Vec::new(),
+            attachment: None,
+            source: None,
+            forward_origin: None,
         }
     }
 }
 
 /// Getters and setters for the struct
 impl ContentItem {
+    pub fn author_id(&self) -> Option<UserId> {
+        self.author_id
+    }
+
     pub fn author(&self) -> &str {
         &self.author
     }
 
+    /// Check whether the item was added by the user.
+    ///
+    /// Legacy items without author id are matched by username.
+    pub fn is_added_by(&self, user_id: UserId, username: &str) -> bool {
+        match self.author_id {
+            Some(author_id) => author_id == user_id,
+            None => self.author == username,
+        }
+    }
+
+    pub fn added_at(&self) -> Option<OffsetDateTime> {
+        self.added_at
+    }
+
     pub fn content(&self) -> &str {
         &self.content
     }
@@ -43,15 +248,185 @@ impl ContentItem {
         self.read_at.is_some()
     }
 
-    #[allow(dead_code)]
+    pub fn read_at(&self) -> Option<OffsetDateTime> {
+        self.read_at
+    }
+
     pub fn set_read(&mut self, read_at: OffsetDateTime) {
         self.read_at.replace(read_at);
     }
 
-    #[allow(dead_code)]
     pub fn set_unread(&mut self) {
         self.read_at = None;
     }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+
+    pub fn archived_at(&self) -> Option<OffsetDateTime> {
+        self.archived_at
+    }
+
+    /// Move the item to the archive, hiding it from the queue
+    pub fn archive(&mut self, archived_at: OffsetDateTime) {
+        self.archived_at.replace(archived_at);
+    }
+
+    /// Return the item from the archive
+    pub fn restore(&mut self) {
+        self.archived_at = None;
+    }
+
+    /// Whether the item is waiting to be watched: it's neither read nor archived
+    pub fn is_queued(&self) -> bool {
+        !self.is_read() && !self.is_archived()
+    }
+
+    pub fn shown_at(&self) -> Option<OffsetDateTime> {
+        self.shown_at
+    }
+
+    /// Record that `/random` showed the item
+    pub fn set_shown(&mut self, shown_at: OffsetDateTime) {
+        self.shown_at.replace(shown_at);
+    }
+
+    pub fn snoozed_until(&self) -> Option<OffsetDateTime> {
+        self.snoozed_until
+    }
+
+    /// Whether the item is snoozed at the moment, snoozes expire by themselves
+    pub fn is_snoozed(&self, now: OffsetDateTime) -> bool {
+        self.snoozed_until.is_some_and(|until| until > now)
+    }
+
+    /// Skip the item in `/random` until the time
+    pub fn snooze(&mut self, until: OffsetDateTime) {
+        self.snoozed_until.replace(until);
+    }
+
+    /// Cancel the snooze, returning the item to `/random` right away
+    pub fn unsnooze(&mut self) {
+        self.snoozed_until = None;
+    }
+
+    pub fn category(&self) -> Option<Category> {
+        self.category
+    }
+
+    pub fn set_category(&mut self, category: Option<Category>) {
+        self.category = category;
+    }
+
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Replace tags of the item, normalizing them
+    pub fn set_tags<T: Borrow<str>>(&mut self, tags: impl IntoIterator<Item = T>) {
+        let mut tags: Vec<_> = tags
+            .into_iter()
+            .map(|tag| normalize_tag(tag.borrow()))
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        tags.sort();
+        tags.dedup();
+
+        self.tags = tags;
+    }
+
+    pub fn links(&self) -> &[Url] {
+        &self.links
+    }
+
+    /// Replace links of the item, keeping the order, but dropping duplicates
+    pub fn set_links(&mut self, links: impl IntoIterator<Item = Url>) {
+        self.links.clear();
+        for link in links {
+            if !self.links.contains(&link) {
+                self.links.push(link);
+            }
+        }
+    }
+
+    pub fn attachment(&self) -> Option<&Attachment> {
+        self.attachment.as_ref()
+    }
+
+    pub fn set_attachment(&mut self, attachment: Option<Attachment>) {
+        self.attachment = attachment;
+    }
+
+    pub fn source(&self) -> Option<Source> {
+        self.source
+    }
+
+    pub fn set_source(&mut self, source: Option<Source>) {
+        self.source = source;
+    }
+
+    pub fn forward_origin(&self) -> Option<&ForwardOrigin> {
+        self.forward_origin.as_ref()
+    }
+
+    pub fn set_forward_origin(&mut self, forward_origin: Option<ForwardOrigin>) {
+        self.forward_origin = forward_origin;
+    }
+
+    /// Check whether the item has the tag – categories count as tags too,
+    /// so `#movie` finds all the movies
+    pub fn matches_tag(&self, tag: &str) -> bool {
+        let tag = normalize_tag(tag);
+
+        self.tags.contains(&tag)
+            || self
+                .category
+                .is_some_and(|category| category.as_str() == tag)
+    }
+
+    /// Category and tags in a single line, like `🎬 movie #horror #short`
+    pub fn labels(&self) -> Option<String> {
+        let labels: Vec<_> = self
+            .category
+            .map(|category| format!("{} {category}", category.emoji()))
+            .into_iter()
+            .chain(self.tags.iter().map(|tag| format!("#{tag}")))
+            .collect();
+
+        (!labels.is_empty()).then(|| labels.join(" "))
+    }
+
+    /// Author of the forwarded message, links missing from the content and the attachment,
+    /// a line for each
+    fn details(&self) -> Option<String> {
+        let details: Vec<_> = self
+            .forward_origin
+            .iter()
+            .map(|origin| format!("↪️ forwarded from {}", origin.author))
+            .chain(
+                self.links
+                    .iter()
+                    .filter(|link| !self.content.contains(link.as_str()))
+                    .map(|link| link.to_string()),
+            )
+            .chain(self.attachment.iter().map(|attachment| {
+                format!("{} {}", attachment.kind.emoji(), attachment.kind.as_str())
+            }))
+            .collect();
+
+        (!details.is_empty()).then(|| details.join("\n"))
+    }
+
+    /// Check whether both items were added by the same user.
+    ///
+    /// Items are compared by author ids, or by usernames when one of them is a legacy item.
+    pub fn has_same_author(&self, other: &ContentItem) -> bool {
+        match (self.author_id, other.author_id) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.author == other.author,
+        }
+    }
 }
 
 /// Methods for sending content items to chats
@@ -60,8 +435,96 @@ impl ContentItem {
     pub fn to_tg_message_text(&self) -> String {
         use clockwork_orange_messages::tg_escape;
 
-        let text = format!("suggested by @{}:\n\n{}", self.author(), self.content());
+        let header = match self.added_at() {
+            Some(added_at) => format!("suggested by @{} on {}:", self.author(), added_at.date()),
+            None => format!("suggested by @{}:", self.author()),
+        };
+
+        let details = self
+            .details()
+            .map(|details| format!("\n\n{}", tg_escape(&details)))
+            .unwrap_or_default();
+        let labels = self
+            .labels()
+            .map(|labels| format!("\n\n{}", tg_escape(&labels)))
+            .unwrap_or_default();
+
+        // read items are struck through, with the date they were watched
+        match self.read_at() {
+            Some(read_at) => format!(
+                "{}\n\n~{}~{details}{labels}\n\n{}",
+                tg_escape(&header),
+                tg_escape(self.content()),
+                tg_escape(&format!("☑️ watched on {}", read_at.date())),
+            ),
+            None => format!(
+                "{}{details}{labels}",
+                tg_escape(&format!("{header}\n\n{}", self.content()))
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that tags are normalized, and categories match as tags
+    fn test_tags() {
+        let mut item = ContentItem::legacy("alice", "https://example.com");
+        item.set_tags(["#Horror", "short", "horror", "#"]);
+        item.set_category(Some(Category::Movie));
+
+        assert_eq!(item.tags(), ["horror", "short"]);
+        assert!(item.matches_tag("#HORROR"));
+        assert!(item.matches_tag("movie"));
+        assert!(!item.matches_tag("book"));
+        assert_eq!(item.labels().as_deref(), Some("🎬 movie #horror #short"));
+
+        assert_eq!(ContentItem::legacy("alice", "text").labels(), None);
+    }
+
+    #[test]
+    /// Test that categories are parsed case-insensitively, with synonyms
+    fn test_category_from_str() {
+        assert_eq!("Movie".parse::<Category>().unwrap(), Category::Movie);
+        assert_eq!("film".parse::<Category>().unwrap(), Category::Movie);
+        assert_eq!("PODCAST".parse::<Category>().unwrap(), Category::Podcast);
+        assert!("song".parse::<Category>().is_err());
+    }
+
+    #[test]
+    /// Test that origins of forwards, links hidden behind the text and attachments
+    /// are shown after the content
+    fn test_details() {
+        let mut item = ContentItem::legacy("alice", "Trailer https://example.com/a");
+        assert_eq!(item.details(), None);
+
+        item.set_links([
+            Url::parse("https://example.com/a").unwrap(),
+            Url::parse("https://example.com/b").unwrap(),
+            Url::parse("https://example.com/b").unwrap(),
+        ]);
+        item.set_attachment(Some(Attachment {
+            kind: AttachmentKind::Photo,
+            file_id: "file".to_string(),
+        }));
+
+        assert_eq!(item.links().len(), 2);
+        assert_eq!(
+            item.details().as_deref(),
+            Some("https://example.com/b\n🖼 photo")
+        );
 
-        tg_escape(&text)
+        item.set_forward_origin(Some(ForwardOrigin {
+            author: "Cinema Club".to_string(),
+            chat_id: Some(ChatId(-1001234567890)),
+            message_id: Some(MessageId(42)),
+        }));
+        assert_eq!(
+            item.details().as_deref(),
+            Some("↪️ forwarded from Cinema Club\nhttps://example.com/b\n🖼 photo")
+        );
     }
 }
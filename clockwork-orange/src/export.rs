@@ -0,0 +1,456 @@
+//! Export and import of watch lists.
+//!
+//! Every item is flattened to a `Record` with its list id and key, so dumps
+//! can be restored into any backend with the keys and read state intact –
+//! buttons in the chat history keep working after the restore.
+//!
+//! Dates are written in RFC 3339, missing values (e.g. author id of legacy items)
+//! are `null` in JSON and empty fields in CSV.
+
+use std::{
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use color_eyre::{
+    eyre::{bail, eyre, WrapErr},
+    Report, Result,
+};
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, MessageId, UserId};
+use time::OffsetDateTime;
+use tracing::info;
+use url::Url;
+
+use crate::{
+    content_item::{Attachment, AttachmentKind, Category, ContentItem, ForwardOrigin, Source},
+    storage::{Key, ListId, StorageBackend},
+};
+
+/// Supported dump formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+}
+
+impl Format {
+    /// File extension for dumps in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "json" => Ok(Self::Json),
+            "csv" => Ok(Self::Csv),
+            _ => Err(eyre!("unknown format `{s}`, expected `json` or `csv`")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Single item of the dump
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Record {
+    list_id: ListId,
+    key: Key,
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    #[serde(with = "time::serde::rfc3339::option")]
+    added_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    read_at: Option<OffsetDateTime>,
+    /// Missing in dumps made before the archive was introduced
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    archived_at: Option<OffsetDateTime>,
+    /// Missing in dumps made before `/random` recorded shown items
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    shown_at: Option<OffsetDateTime>,
+    /// Missing in dumps made before items could be snoozed
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    snoozed_until: Option<OffsetDateTime>,
+    /// Missing in dumps made before categories and tags were introduced
+    #[serde(default)]
+    category: Option<Category>,
+    /// Space-separated, so CSV dumps keep a single column for them
+    #[serde(default)]
+    tags: String,
+    /// Space-separated too, missing in dumps made before links and attachments were introduced
+    #[serde(default)]
+    links: String,
+    #[serde(default)]
+    attachment_kind: Option<AttachmentKind>,
+    #[serde(default)]
+    attachment_file_id: Option<String>,
+    /// Message the item was added from, missing in dumps made before it was recorded
+    #[serde(default)]
+    source_chat_id: Option<ChatId>,
+    #[serde(default)]
+    source_message_id: Option<i32>,
+    /// Origin of forwarded messages, flattened so CSV dumps have a column for each field
+    #[serde(default)]
+    forward_author: Option<String>,
+    #[serde(default)]
+    forward_chat_id: Option<ChatId>,
+    #[serde(default)]
+    forward_message_id: Option<i32>,
+}
+
+impl Record {
+    fn new(list: ListId, key: Key, item: ContentItem) -> Self {
+        Self {
+            list_id: list,
+            key,
+            author_id: item.author_id(),
+            author: item.author().to_string(),
+            content: item.content().to_string(),
+            added_at: item.added_at(),
+            read_at: item.read_at(),
+            archived_at: item.archived_at(),
+            shown_at: item.shown_at(),
+            snoozed_until: item.snoozed_until(),
+            category: item.category(),
+            tags: item.tags().join(" "),
+            links: item
+                .links()
+                .iter()
+                .map(Url::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+            attachment_kind: item.attachment().map(|attachment| attachment.kind),
+            attachment_file_id: item
+                .attachment()
+                .map(|attachment| attachment.file_id.clone()),
+            source_chat_id: item.source().map(|source| source.chat_id),
+            source_message_id: item.source().map(|source| source.message_id.0),
+            forward_author: item.forward_origin().map(|origin| origin.author.clone()),
+            forward_chat_id: item.forward_origin().and_then(|origin| origin.chat_id),
+            forward_message_id: item
+                .forward_origin()
+                .and_then(|origin| origin.message_id)
+                .map(|message_id| message_id.0),
+        }
+    }
+
+    /// Split record into the list, key and the item itself
+    fn into_parts(self) -> Result<(ListId, Key, ContentItem)> {
+        if !self.key.is_valid() {
+            bail!("key {:?} is neither a ULID nor a message id", self.key);
+        }
+
+        let mut item = match (self.author_id, self.added_at) {
+            (Some(author_id), Some(added_at)) => {
+                ContentItem::new(author_id, self.author, self.content, added_at)
+            }
+            _ => ContentItem::legacy(self.author, self.content),
+        };
+        if let Some(read_at) = self.read_at {
+            item.set_read(read_at);
+        }
+        if let Some(archived_at) = self.archived_at {
+            item.archive(archived_at);
+        }
+        if let Some(shown_at) = self.shown_at {
+            item.set_shown(shown_at);
+        }
+        if let Some(snoozed_until) = self.snoozed_until {
+            item.snooze(snoozed_until);
+        }
+        item.set_category(self.category);
+        item.set_tags(self.tags.split_whitespace());
+        item.set_links(
+            self.links
+                .split_whitespace()
+                .map(Url::parse)
+                .collect::<Result<Vec<_>, _>>()
+                .wrap_err("invalid link")?,
+        );
+        if let (Some(kind), Some(file_id)) = (self.attachment_kind, self.attachment_file_id) {
+            item.set_attachment(Some(Attachment { kind, file_id }));
+        }
+        if let (Some(chat_id), Some(message_id)) = (self.source_chat_id, self.source_message_id) {
+            item.set_source(Some(Source {
+                chat_id,
+                message_id: MessageId(message_id),
+            }));
+        }
+        item.set_forward_origin(self.forward_author.map(|author| ForwardOrigin {
+            author,
+            chat_id: self.forward_chat_id,
+            message_id: self.forward_message_id.map(MessageId),
+        }));
+
+        Ok((self.list_id, self.key, item))
+    }
+}
+
+/// Collect all the items of the given lists, read ones included.
+///
+/// Records are sorted by list and key, so dumps of the same data are identical.
+#[tracing::instrument(err, skip(storage))]
+pub async fn export<B: StorageBackend>(storage: &B, lists: &[ListId]) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for list in lists {
+        let items = storage
+            .dump(list)
+            .await
+            .wrap_err_with(|| format!("failed to dump list {list}"))?;
+
+        records.extend(
+            items
+                .into_iter()
+                .map(|(key, item)| Record::new(*list, key, item)),
+        );
+    }
+    records.sort_by(|a, b| (a.list_id, &a.key).cmp(&(b.list_id, &b.key)));
+
+    info!(
+        "Exported {} items from {} lists",
+        records.len(),
+        lists.len()
+    );
+
+    Ok(records)
+}
+
+/// Store all the records, overwriting items with the same keys.
+///
+/// Records are checked before anything is stored, an invalid one fails the whole import.
+/// Returns number of imported items.
+#[tracing::instrument(err, skip(storage, records))]
+pub async fn import<B: StorageBackend>(storage: &mut B, records: Vec<Record>) -> Result<usize> {
+    let count = records.len();
+    let parts = records
+        .into_iter()
+        .enumerate()
+        .map(|(index, record)| {
+            record
+                .into_parts()
+                .wrap_err_with(|| format!("invalid record #{}", index + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    for (list, key, item) in parts {
+        storage
+            .set(&list, &key, item)
+            .await
+            .wrap_err_with(|| format!("failed to import item {key:?} to list {list}"))?;
+    }
+
+    info!("Imported {count} items");
+
+    Ok(count)
+}
+
+/// Write records in the given format
+pub fn write_records(records: &[Record], format: Format, writer: impl Write) -> Result<()> {
+    match format {
+        Format::Json => {
+            serde_json::to_writer_pretty(writer, records).wrap_err("failed to write JSON")?;
+        }
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for record in records {
+                writer
+                    .serialize(record)
+                    .wrap_err("failed to write CSV record")?;
+            }
+            writer.flush().wrap_err("failed to flush CSV")?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Read records written by `write_records`
+pub fn read_records(format: Format, reader: impl Read) -> Result<Vec<Record>> {
+    match format {
+        Format::Json => serde_json::from_reader(reader).wrap_err("failed to read JSON"),
+        Format::Csv => csv::Reader::from_reader(reader)
+            .deserialize()
+            .map(|record| record.wrap_err("failed to read CSV record"))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::MemoryStorage;
+
+    use super::*;
+
+    fn list(id: i64) -> ListId {
+        ChatId(id).into()
+    }
+
+    /// Storage with new, read and legacy items in two lists
+    async fn storage() -> MemoryStorage {
+        let mut storage = MemoryStorage::new();
+
+        let item = ContentItem::new(
+            UserId(1),
+            "alice",
+            "https://example.com/new, with \"quotes\"\nand newlines",
+            OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap(),
+        );
+        storage.set(&list(1), &Key::generate(), item).await.unwrap();
+
+        let mut read = ContentItem::new(
+            UserId(2),
+            "bob",
+            "https://example.com/read",
+            OffsetDateTime::UNIX_EPOCH,
+        );
+        read.set_read(
+            OffsetDateTime::from_unix_timestamp_nanos(1_700_000_000_123_456_789).unwrap(),
+        );
+        storage.set(&list(1), &Key::generate(), read).await.unwrap();
+
+        let mut legacy = ContentItem::legacy("alice", "https://example.com/legacy");
+        legacy.archive(OffsetDateTime::UNIX_EPOCH);
+        legacy.set_shown(OffsetDateTime::UNIX_EPOCH);
+        legacy.snooze(OffsetDateTime::UNIX_EPOCH);
+        legacy.set_category(Some(Category::Movie));
+        legacy.set_tags(["horror", "short"]);
+        legacy.set_links([Url::parse("https://example.com/trailer").unwrap()]);
+        legacy.set_attachment(Some(Attachment {
+            kind: AttachmentKind::Photo,
+            file_id: "file".to_string(),
+        }));
+        legacy.set_source(Some(Source {
+            chat_id: ChatId(2),
+            message_id: MessageId(10),
+        }));
+        legacy.set_forward_origin(Some(ForwardOrigin {
+            author: "Cinema Club".to_string(),
+            chat_id: Some(ChatId(-1001234567890)),
+            message_id: Some(MessageId(42)),
+        }));
+        storage
+            .set(&list(2), &Key::generate(), legacy)
+            .await
+            .unwrap();
+
+        storage
+    }
+
+    async fn roundtrip(format: Format) {
+        let source = storage().await;
+        let lists = [list(1), list(2)];
+        let records = export(&source, &lists).await.unwrap();
+        assert_eq!(records.len(), 3);
+
+        let mut bytes = Vec::new();
+        write_records(&records, format, &mut bytes).unwrap();
+        let read = read_records(format, bytes.as_slice()).unwrap();
+        assert_eq!(read, records);
+
+        let mut target = MemoryStorage::new();
+        assert_eq!(import(&mut target, read).await.unwrap(), 3);
+        for list in &lists {
+            assert_eq!(
+                target.dump(list).await.unwrap(),
+                source.dump(list).await.unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    /// Test that items survive JSON export and import unchanged
+    async fn test_json_roundtrip() {
+        roundtrip(Format::Json).await;
+    }
+
+    #[tokio::test]
+    /// Test that items survive CSV export and import unchanged
+    async fn test_csv_roundtrip() {
+        roundtrip(Format::Csv).await;
+    }
+
+    #[tokio::test]
+    /// Test that records with keys which don't fit into buttons fail the whole import
+    async fn test_import_invalid_key() {
+        let (list, key, item) = (list(1), Key::generate(), ContentItem::legacy("alice", "x"));
+        let valid = Record::new(list, key.clone(), item.clone());
+        let legacy = Record::new(list, Key::from("123".to_string()), item.clone());
+        let invalid = Record::new(list, Key::from("x".repeat(40)), item);
+
+        let mut storage = MemoryStorage::new();
+        let err = import(&mut storage, vec![valid.clone(), legacy.clone(), invalid])
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("invalid record #3"));
+        assert!(storage.get(&list, &key).await.unwrap().is_none());
+
+        assert_eq!(import(&mut storage, vec![valid, legacy]).await.unwrap(), 2);
+    }
+
+    #[tokio::test]
+    /// Test that corrupted links fail the import instead of being dropped
+    async fn test_import_invalid_link() {
+        let mut record = Record::new(list(1), Key::generate(), ContentItem::legacy("alice", "x"));
+        record.links = "https://example.com not-a-link".to_string();
+
+        let err = import(&mut MemoryStorage::new(), vec![record])
+            .await
+            .unwrap_err();
+        assert!(format!("{err:#}").contains("invalid link"));
+    }
+
+    #[test]
+    /// Test that dumps made before the archive, shown and snoozed items, categories,
+    /// tags, links, attachments and sources were introduced are still readable
+    fn test_read_old_dumps() {
+        let csv = indoc::indoc! {"
+            list_id,key,author_id,author,content,added_at,read_at
+            1,01HX0000000000000000000000,1,alice,https://example.com,1970-01-01T00:00:00Z,
+        "};
+        let json = r#"[{
+            "list_id": 1,
+            "key": "01HX0000000000000000000000",
+            "author_id": 1,
+            "author": "alice",
+            "content": "https://example.com",
+            "added_at": "1970-01-01T00:00:00Z",
+            "read_at": null
+        }]"#;
+
+        let from_csv = read_records(Format::Csv, csv.as_bytes()).unwrap();
+        let from_json = read_records(Format::Json, json.as_bytes()).unwrap();
+
+        assert_eq!(from_csv, from_json);
+        assert_eq!(from_csv[0].archived_at, None);
+        assert_eq!(from_csv[0].shown_at, None);
+        assert_eq!(from_csv[0].snoozed_until, None);
+        assert_eq!(from_csv[0].category, None);
+        assert_eq!(from_csv[0].tags, "");
+        assert_eq!(from_csv[0].links, "");
+        assert_eq!(from_csv[0].attachment_kind, None);
+        assert_eq!(from_csv[0].source_chat_id, None);
+        assert_eq!(from_csv[0].forward_author, None);
+    }
+
+    #[test]
+    /// Test that formats are parsed case-insensitively and unknown ones are rejected
+    fn test_format_from_str() {
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert_eq!(" CSV ".parse::<Format>().unwrap(), Format::Csv);
+        assert!("xml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Links in the text of items – finding them and normalizing,
+//! so the same page shared from different apps is recognized as one.
+
+use url::Url;
+
+/// Query parameters which only track where the link was shared from
+const TRACKING_PARAMS: [&str; 14] = [
+    "fbclid", "gclid", "yclid", "igshid", "igsh", "si", "feature", "ref", "ref_", "ref_src",
+    "mc_cid", "mc_eid", "_ga", "share",
+];
+
+/// Prefixes of mobile and alternative hosts of the same site
+const HOST_PREFIXES: [&str; 3] = ["www.", "m.", "mobile."];
+
+/// Find http(s) links in the text, along with the words they were found in
+pub fn find(text: &str) -> impl Iterator<Item = (&str, Url)> {
+    text.split_whitespace().filter_map(|word| {
+        // punctuation after the link belongs to the sentence
+        let word = word.trim_end_matches([',', '.', ';', '!', ')']);
+        let url = Url::parse(word).ok()?;
+
+        matches!(url.scheme(), "http" | "https").then_some((word, url))
+    })
+}
+
+/// Host without `www.`, `m.` and similar prefixes
+pub fn host(url: &Url) -> Option<&str> {
+    let host = url.host_str()?;
+
+    Some(
+        HOST_PREFIXES
+            .iter()
+            .find_map(|prefix| host.strip_prefix(prefix))
+            .unwrap_or(host),
+    )
+}
+
+/// Normalize the link – drop tracking parameters, the fragment and the trailing slash,
+/// use https and the desktop host, expand `youtu.be` short links
+pub fn normalize(url: &Url) -> Url {
+    let mut normalized = url.clone();
+    normalized.set_fragment(None);
+    // http and https can only be changed between each other, others are kept
+    let _ = normalized.set_scheme("https");
+    if let Some(host) = host(url) {
+        let _ = normalized.set_host(Some(host));
+    }
+
+    if normalized.host_str() == Some("youtu.be") {
+        let id = normalized.path().trim_matches('/').to_string();
+        normalized = Url::parse("https://youtube.com/watch").expect("static URL is valid");
+        normalized.query_pairs_mut().append_pair("v", &id);
+        // timestamps and playlists are kept
+        for (name, value) in url.query_pairs() {
+            normalized.query_pairs_mut().append_pair(&name, &value);
+        }
+    }
+
+    let query: Vec<(String, String)> = normalized
+        .query_pairs()
+        .filter(|(name, _)| !name.starts_with("utm_") && !TRACKING_PARAMS.contains(&name.as_ref()))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        normalized.set_query(None);
+    } else {
+        normalized.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    if normalized.path().len() > 1 && normalized.path().ends_with('/') {
+        let path = normalized.path().trim_end_matches('/').to_string();
+        normalized.set_path(&path);
+    }
+
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalize(url: &str) -> String {
+        super::normalize(&Url::parse(url).unwrap()).into()
+    }
+
+    #[test]
+    /// Test that links are found in the text, without the punctuation after them
+    fn test_find() {
+        let links: Vec<_> =
+            find("Look: https://example.com/a, and http://example.org). ftp://x.org")
+                .map(|(word, url)| (word, url.to_string()))
+                .collect();
+
+        assert_eq!(
+            links,
+            [
+                ("https://example.com/a", "https://example.com/a".to_string()),
+                ("http://example.org", "http://example.org/".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    /// Test that the same page shared in different ways is normalized to the same link
+    fn test_normalize() {
+        let youtube = "https://youtube.com/watch?v=dQw4w9WgXcQ";
+        assert_eq!(normalize("https://youtu.be/dQw4w9WgXcQ?si=abc"), youtube);
+        assert_eq!(
+            normalize("http://m.youtube.com/watch?v=dQw4w9WgXcQ&feature=share#comments"),
+            youtube
+        );
+        assert_eq!(
+            normalize("https://www.youtube.com/watch?utm_source=tg&v=dQw4w9WgXcQ"),
+            youtube
+        );
+        assert_eq!(
+            normalize("https://youtu.be/dQw4w9WgXcQ?t=42"),
+            "https://youtube.com/watch?v=dQw4w9WgXcQ&t=42"
+        );
+
+        assert_eq!(
+            normalize("https://mobile.twitter.com/user/status/1/"),
+            "https://twitter.com/user/status/1"
+        );
+        assert_eq!(normalize("https://example.com/"), "https://example.com/");
+        assert_eq!(
+            normalize("https://example.com/search?q=film&page=2"),
+            "https://example.com/search?q=film&page=2"
+        );
+    }
+}
@@ -79,10 +79,7 @@ where
             .serve(router.into_make_service())
             .with_graceful_shutdown(stop_flag)
             .await
-            .map_err(|err| {
-                stop_token.stop();
-                err
-            })
+            .inspect_err(|_| stop_token.stop())
             .expect("Axum server error");
     });
 
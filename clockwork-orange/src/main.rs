@@ -1,22 +1,77 @@
+use std::fmt::Debug;
+
+use clap::Parser;
+use teloxide::types::ChatId;
 use tracing::{debug, info};
 use tracing_subscriber::{layer::SubscriberExt, registry::Registry, EnvFilter};
 use tracing_tree::HierarchicalLayer;
 
 use crate::{
+    cli::{Cli, Subcommand},
     config::{BotMode, Config, StorageKind},
-    storage::{MemoryStorage, RedisStorage},
+    storage::{
+        MemoryStorage, PostgresStorage, RedisStorage, SqliteStorage, Storage, StorageBackend,
+    },
 };
 
 mod bot;
+mod classifier;
+mod cli;
 mod config;
 mod content_item;
+mod export;
+mod links;
 mod listeners;
+mod settings;
 mod storage;
+mod transfer;
+
+/// Create bot on top of given storage and start listening for updates
+async fn run<B: StorageBackend + Debug + 'static>(
+    storage: Storage<B>,
+    config: &Config,
+) -> color_eyre::Result<()> {
+    let (bot, dispatcher) = bot::create_bot_and_dispatcher(storage.clone(), config).await?;
+
+    match config.bot_mode {
+        BotMode::Polling => {
+            listeners::start_polling(dispatcher, bot).await?;
+        }
+        BotMode::Webhook => {
+            listeners::start_webhook(dispatcher, bot, storage, config).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Execute the subcommand on top of given storage
+async fn execute<B: StorageBackend + Debug + 'static>(
+    mut storage: Storage<B>,
+    config: &Config,
+    command: Subcommand,
+) -> color_eyre::Result<()> {
+    match command {
+        Subcommand::Run => run(storage, config).await,
+        Subcommand::Export {
+            format,
+            list,
+            output,
+        } => cli::export(&*storage, format, list, output.as_deref()).await,
+        Subcommand::Import { format, input } => cli::import(&mut *storage, format, &input).await,
+        Subcommand::Migrate { target, dry_run } => {
+            cli::migrate(&*storage, &target, config.redis_timeouts(), dry_run).await
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
 
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Subcommand::Run);
+
     let layer = HierarchicalLayer::default()
         .with_indent_lines(true)
         .with_bracketed_fields(true)
@@ -31,40 +86,44 @@ async fn main() -> color_eyre::Result<()> {
 
     match config.storage {
         StorageKind::InMemory => {
-            info!("Creating bot with in-memory storage");
+            info!("Using in-memory storage");
             let storage = MemoryStorage::new().into_storage();
 
-            let (bot, dispatcher) =
-                bot::create_bot_and_dispatcher(storage.clone(), &config).await?;
-
-            match config.bot_mode {
-                BotMode::Polling => {
-                    listeners::start_polling(dispatcher, bot).await?;
-                }
-                BotMode::Webhook => {
-                    listeners::start_webhook(dispatcher, bot, storage, &config).await?;
-                }
-            }
+            execute(storage, &config, command).await?;
         }
         StorageKind::Redis => {
-            info!("Creating bot with redis storage");
+            info!("Using redis storage");
             let redis_url = config.redis_url.as_ref().expect("REDIS_URL unspecified");
-            let storage = RedisStorage::new(redis_url).await?.into_storage();
-
-            let (bot, dispatcher) =
-                bot::create_bot_and_dispatcher(storage.clone(), &config).await?;
-
-            match config.bot_mode {
-                BotMode::Polling => {
-                    listeners::start_polling(dispatcher, bot).await?;
-                }
-                BotMode::Webhook => {
-                    listeners::start_webhook(dispatcher, bot, storage, &config).await?;
-                }
+            let mut storage = RedisStorage::new(redis_url, config.redis_timeouts()).await?;
+            if let Some(list) = config.redis_legacy_list_id {
+                storage.migrate_legacy_keys(&ChatId(list).into()).await?;
             }
+            let storage = storage.into_storage();
+
+            execute(storage, &config, command).await?;
+        }
+        StorageKind::Sqlite => {
+            info!("Using sqlite storage");
+            let sqlite_path = config
+                .sqlite_path
+                .as_ref()
+                .expect("SQLITE_PATH unspecified");
+            let storage = SqliteStorage::new(sqlite_path).await?.into_storage();
+
+            execute(storage, &config, command).await?;
+        }
+        StorageKind::Postgres => {
+            info!("Using postgres storage");
+            let database_url = config
+                .database_url
+                .as_ref()
+                .expect("DATABASE_URL unspecified");
+            let storage = PostgresStorage::new(database_url).await?.into_storage();
+
+            execute(storage, &config, command).await?;
         }
     };
-    debug!("Bot created");
+    debug!("Done");
 
     Ok(())
 }
@@ -0,0 +1,64 @@
+//! Per-chat settings, stored alongside the list of the chat.
+//!
+//! Settings are serialized as JSON, every field has a default,
+//! so settings stored by older releases are still readable.
+
+use serde::{Deserialize, Serialize};
+use time::Duration;
+
+use crate::storage::{RandomOptions, Weighting};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ChatSettings {
+    /// Defaults of `/random`, its arguments override them
+    pub random: RandomSettings,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct RandomSettings {
+    pub weighting: Weighting,
+    /// Pick only items added at most this many days ago
+    pub max_age_days: Option<u16>,
+    /// Don't pick items shown by `/random` during this many days
+    pub no_repeat_days: Option<u16>,
+}
+
+impl RandomSettings {
+    /// Options for picking a random item with these settings
+    pub fn to_options(&self) -> RandomOptions {
+        let days = |days: u16| Duration::days(days.into());
+
+        RandomOptions {
+            weighting: self.weighting,
+            max_age: self.max_age_days.map(days),
+            no_repeat: self.no_repeat_days.map(days),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test that missing fields get defaults, so older settings are readable
+    fn test_deserialize_partial() {
+        let settings: ChatSettings =
+            serde_json::from_str(r#"{"random": {"weighting": "older"}}"#).unwrap();
+
+        assert_eq!(
+            settings.random,
+            RandomSettings {
+                weighting: Weighting::Older,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            serde_json::from_str::<ChatSettings>("{}").unwrap(),
+            ChatSettings::default()
+        );
+    }
+}
@@ -1,23 +1,54 @@
 use std::{
     collections::HashMap,
+    fmt,
     ops::{Deref, DerefMut},
 };
 
 use color_eyre::{eyre::eyre, Result};
-use rand::Rng;
+use serde::{Deserialize, Serialize};
+use teloxide::types::{ChatId, UserId};
 use time::OffsetDateTime;
 
-use crate::content_item::ContentItem;
+use crate::{content_item::ContentItem, settings::ChatSettings};
+
+#[cfg(test)]
+mod conformance;
 
 mod memory;
 pub use memory::MemoryStorage;
 
+mod postgres;
+pub use postgres::PostgresStorage;
+
+mod random;
+pub use random::{AuthorFilter, RandomOptions, Weighting};
+
 mod redis;
-pub use self::redis::RedisStorage;
+pub use self::redis::{RedisStorage, RedisTimeouts};
 
-#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+mod sqlite;
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
 pub struct Key(String);
 
+impl Key {
+    /// Generate new globally unique key.
+    ///
+    /// ULIDs are 26 characters long, so keys fit into callback payloads
+    /// alongside the callback kind.
+    pub fn generate() -> Self {
+        Key(ulid::Ulid::new().to_string())
+    }
+
+    /// Check that the key is a ULID or a legacy message id,
+    /// other keys don't fit into callback payloads
+    pub fn is_valid(&self) -> bool {
+        self.0.parse::<ulid::Ulid>().is_ok() || self.0.parse::<i32>().is_ok()
+    }
+}
+
 impl From<String> for Key {
     fn from(val: String) -> Key {
         Key(val)
@@ -30,6 +61,51 @@ impl AsRef<str> for Key {
     }
 }
 
+/// Identifier of a watch list – every Telegram chat has its own list
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct ListId(i64);
+
+impl From<ChatId> for ListId {
+    fn from(chat_id: ChatId) -> ListId {
+        ListId(chat_id.0)
+    }
+}
+
+impl fmt::Display for ListId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Which read items to return from `StorageBackend::get_history`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryFilter {
+    /// Items read at this time or later
+    pub from: Option<OffsetDateTime>,
+    /// Items read before this time
+    pub to: Option<OffsetDateTime>,
+    /// Items added by this user, username is used to find legacy items
+    pub author: Option<(UserId, String)>,
+}
+
+impl HistoryFilter {
+    /// Check whether the item belongs to the history and matches the filter
+    pub fn matches(&self, item: &ContentItem) -> bool {
+        let Some(read_at) = item.read_at() else {
+            return false;
+        };
+
+        !item.is_archived()
+            && self.from.is_none_or(|from| read_at >= from)
+            && self.to.is_none_or(|to| read_at < to)
+            && self
+                .author
+                .as_ref()
+                .is_none_or(|(user_id, username)| item.is_added_by(*user_id, username))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Storage<B: StorageBackend> {
     backend: B,
@@ -50,25 +126,79 @@ impl<B: StorageBackend> DerefMut for Storage<B> {
 }
 
 #[async_trait::async_trait]
-/// Basic storage trait, all methods, except `get` should return only unread items
+/// Basic storage trait, all methods, except `get`, `dump`, `get_archived` and `get_history`
+/// should return only queued items – unread and not archived
+///
+/// Items are grouped into lists (one per chat), every method operates on a single list only
 pub trait StorageBackend: Send + Sync + Clone + std::fmt::Debug {
     /// Set item to storage
-    async fn set(&mut self, key: &Key, value: ContentItem) -> Result<()>;
+    async fn set(&mut self, list: &ListId, key: &Key, value: ContentItem) -> Result<()>;
 
     /// Get specific item from storage
     ///
     /// # Caution
     /// May return read items
-    async fn get(&self, key: &Key) -> Result<Option<ContentItem>>;
+    async fn get(&self, list: &ListId, key: &Key) -> Result<Option<ContentItem>>;
+
+    /// Atomically modify item with `f`, returning the updated item
+    /// or `None` if there is no such item
+    ///
+    /// `f` may be called several times if the item was modified concurrently,
+    /// so it shouldn't have side effects
+    async fn update<F>(&mut self, list: &ListId, key: &Key, f: F) -> Result<Option<ContentItem>>
+    where
+        F: FnMut(&mut ContentItem) + Send;
+
+    /// Get all queued items from the list, filtering out read and archived ones
+    async fn get_all(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>>;
+
+    /// Get all items from the list, including read ones – used for backups
+    async fn dump(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>>;
 
-    /// Get all items from storage, filtering unread ones
-    async fn get_all(&self) -> Result<HashMap<Key, ContentItem>>;
+    /// Get ids of all the lists which have any items, read or unread, or saved chat settings
+    async fn get_lists(&self) -> Result<Vec<ListId>>;
 
-    /// Get items which this user has added
+    /// Get archived items of the list, read or unread
     #[tracing::instrument(err, skip(self))]
-    async fn get_user_items(&self, user: &str) -> Result<HashMap<Key, ContentItem>> {
-        let mut map = self.get_all().await?;
-        map.retain(|_, item| item.author() == user);
+    async fn get_archived(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        let mut map = self.dump(list).await?;
+        map.retain(|_, item| item.is_archived());
+
+        Ok(map)
+    }
+
+    /// Get read items matching the filter, sorted by `read_at`, oldest first.
+    ///
+    /// Archived items aren't part of the history.
+    #[tracing::instrument(err, skip(self))]
+    async fn get_history(
+        &self,
+        list: &ListId,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<(Key, ContentItem)>> {
+        let mut items: Vec<_> = self
+            .dump(list)
+            .await?
+            .into_iter()
+            .filter(|(_, item)| filter.matches(item))
+            .collect();
+        items.sort_by_key(|(key, item)| (item.read_at(), key.clone()));
+
+        Ok(items)
+    }
+
+    /// Get items which this user has added to the list
+    ///
+    /// Username is used to find legacy items, which have no author id
+    #[tracing::instrument(err, skip(self))]
+    async fn get_user_items(
+        &self,
+        list: &ListId,
+        user_id: UserId,
+        username: &str,
+    ) -> Result<HashMap<Key, ContentItem>> {
+        let mut map = self.get_all(list).await?;
+        map.retain(|_, item| item.is_added_by(user_id, username));
 
         Ok(map)
     }
@@ -77,41 +207,106 @@ pub trait StorageBackend: Send + Sync + Clone + std::fmt::Debug {
     /// through Storage trait whenever possible
     async fn get_now(&self) -> Result<OffsetDateTime>;
 
-    /// Delete item from storage – unused currently
-    async fn delete(&mut self, key: &Key) -> Result<()>;
+    /// Permanently delete item from storage – unused currently,
+    /// the bot moves items to the archive instead, see `archive`
+    #[allow(dead_code)]
+    async fn delete(&mut self, list: &ListId, key: &Key) -> Result<()>;
 
-    /// Mark item as "read" – user has seen it and wants to remove it from his queue
+    /// Mark item as "read" – user has seen it and wants to remove it from his queue.
+    ///
+    /// Read time of already read items is kept. Returns the updated item.
+    #[tracing::instrument(err, skip(self))]
+    async fn mark_as_read(&mut self, list: &ListId, key: &Key) -> Result<ContentItem> {
+        let now = self.get_now().await?;
+        self.update(list, key, |item| {
+            if !item.is_read() {
+                item.set_read(now)
+            }
+        })
+        .await?
+        .ok_or_else(|| eyre!("Item not found"))
+    }
+
+    /// Mark item as "unread" – return it to the queue, e.g. when it was marked as read by mistake.
+    ///
+    /// Returns the updated item.
     #[tracing::instrument(err, skip(self))]
-    async fn mark_as_read(&mut self, key: &Key) -> Result<()> {
-        let mut item = self
-            .get(key)
+    async fn mark_as_unread(&mut self, list: &ListId, key: &Key) -> Result<ContentItem> {
+        self.update(list, key, |item| item.set_unread())
             .await?
-            .ok_or_else(|| eyre!("Item not found"))?;
+            .ok_or_else(|| eyre!("Item not found"))
+    }
+
+    /// Move item to the archive – it's hidden from the queue, but can be restored.
+    /// Returns the updated item.
+    #[tracing::instrument(err, skip(self))]
+    async fn archive(&mut self, list: &ListId, key: &Key) -> Result<ContentItem> {
+        let now = self.get_now().await?;
+        self.update(list, key, |item| item.archive(now))
+            .await?
+            .ok_or_else(|| eyre!("Item not found"))
+    }
+
+    /// Return item from the archive to the list. Returns the updated item.
+    #[tracing::instrument(err, skip(self))]
+    async fn restore(&mut self, list: &ListId, key: &Key) -> Result<ContentItem> {
+        self.update(list, key, |item| item.restore())
+            .await?
+            .ok_or_else(|| eyre!("Item not found"))
+    }
 
-        item.set_read(self.get_now().await?);
-        self.set(key, item.clone()).await?;
+    /// Record that `/random` showed the item, see `RandomOptions::no_repeat`
+    #[tracing::instrument(err, skip(self))]
+    async fn mark_as_shown(&mut self, list: &ListId, key: &Key) -> Result<()> {
+        let now = self.get_now().await?;
+        self.update(list, key, |item| item.set_shown(now))
+            .await?
+            .ok_or_else(|| eyre!("Item not found"))?;
 
         Ok(())
     }
 
-    /// Get random unread item from storage
-    #[tracing::instrument(fields(random_key), err, skip(self))]
-    async fn get_random(&self) -> Result<Option<(Key, ContentItem)>> {
-        let items = self.get_all().await?;
-        let mut rng = rand::thread_rng();
-        if items.is_empty() {
-            return Ok(None);
-        }
-        let index = rng.gen_range(0..items.len());
+    /// Snooze item until the time – `get_random` skips it till then, see `ContentItem::is_snoozed`.
+    ///
+    /// Returns the updated item.
+    #[tracing::instrument(err, skip(self))]
+    async fn snooze(
+        &mut self,
+        list: &ListId,
+        key: &Key,
+        until: OffsetDateTime,
+    ) -> Result<ContentItem> {
+        self.update(list, key, |item| item.snooze(until))
+            .await?
+            .ok_or_else(|| eyre!("Item not found"))
+    }
 
-        let (key, item) = items
-            .iter()
-            .nth(index)
-            .expect("this should never happen, but it did");
-        tracing::Span::current().record("random_key", &key.0);
-        Ok(Some((key.clone(), item.clone())))
+    /// Cancel the snooze of the item. Returns the updated item.
+    #[tracing::instrument(err, skip(self))]
+    async fn unsnooze(&mut self, list: &ListId, key: &Key) -> Result<ContentItem> {
+        self.update(list, key, |item| item.unsnooze())
+            .await?
+            .ok_or_else(|| eyre!("Item not found"))
+    }
+
+    /// Get random queued item from the list, picked according to the options.
+    ///
+    /// Snoozed items are never picked.
+    #[tracing::instrument(err, skip(self))]
+    async fn get_random(
+        &self,
+        list: &ListId,
+        options: &RandomOptions,
+    ) -> Result<Option<(Key, ContentItem)>> {
+        random::get_random(self, list, options).await
     }
 
+    /// Get settings of the list's chat, defaults if they were never changed
+    async fn get_settings(&self, list: &ListId) -> Result<ChatSettings>;
+
+    /// Store settings of the list's chat
+    async fn set_settings(&mut self, list: &ListId, settings: &ChatSettings) -> Result<()>;
+
     /// Check that storage is live and can be used
     async fn health_check(&self) -> Result<()>;
 }
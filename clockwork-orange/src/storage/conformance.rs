@@ -0,0 +1,776 @@
+//! Contract every `StorageBackend` implementation should fulfil.
+//!
+//! Checks are generic over the backend, `conformance_tests!` generates a test for each
+//! of them in the backend's test module. Every check works in its own random list,
+//! so backends with shared state (Redis, Postgres) can run them in parallel.
+
+use teloxide::types::{ChatId, MessageId, UserId};
+use time::OffsetDateTime;
+use url::Url;
+
+use super::{AuthorFilter, ContentItem, HistoryFilter, Key, ListId, RandomOptions, StorageBackend};
+use crate::{
+    content_item::{Attachment, AttachmentKind, Category, ForwardOrigin, Source},
+    settings::ChatSettings,
+};
+
+/// Generate conformance tests for the backend.
+///
+/// Accepts expression creating the storage, evaluated in async context,
+/// and optional attributes for every test (e.g. `#[ignore]`).
+macro_rules! conformance_tests {
+    ($storage:expr $(, #[$attr:meta])*) => {
+        mod conformance {
+            use super::*;
+
+            conformance_tests!(@tests [$(#[$attr])*] $storage;
+                set_get,
+                set_get_labels,
+                get_missing,
+                delete,
+                get_all_filters_read,
+                get_user_items,
+                get_random,
+                get_random_empty,
+                get_random_filtered,
+                snooze,
+                mark_as_read,
+                mark_as_unread,
+                update,
+                concurrent_updates,
+                lists_are_isolated,
+                dump,
+                get_lists,
+                archive,
+                history,
+                settings,
+            );
+        }
+    };
+    (@tests [$($attrs:tt)*] $storage:expr; $check:ident, $($rest:ident,)*) => {
+        #[tokio::test]
+        $($attrs)*
+        async fn $check() {
+            crate::storage::conformance::$check($storage).await;
+        }
+
+        conformance_tests!(@tests [$($attrs)*] $storage; $($rest,)*);
+    };
+    (@tests [$($attrs:tt)*] $storage:expr;) => {};
+}
+
+pub(super) use conformance_tests;
+
+fn random_list() -> ListId {
+    ListId(rand::random())
+}
+
+fn item(author_id: u64, author: &str, content: &str) -> ContentItem {
+    ContentItem::new(
+        UserId(author_id),
+        author,
+        content,
+        OffsetDateTime::UNIX_EPOCH,
+    )
+}
+
+/// Items can be stored, read back and overwritten
+pub(super) async fn set_get<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    let mut item = item(1, "alice", "https://example.com");
+
+    storage.set(&list, &key, item.clone()).await.unwrap();
+    assert_eq!(storage.get(&list, &key).await.unwrap(), Some(item.clone()));
+
+    item.set_content("https://example.com/updated");
+    storage.set(&list, &key, item.clone()).await.unwrap();
+    assert_eq!(storage.get(&list, &key).await.unwrap(), Some(item));
+
+    let legacy = ContentItem::legacy("bob", "https://example.com/legacy");
+    storage.set(&list, &key, legacy.clone()).await.unwrap();
+    assert_eq!(storage.get(&list, &key).await.unwrap(), Some(legacy));
+}
+
+/// Category, tags, links, the attachment, the source and the forward origin
+/// are stored with the item
+pub(super) async fn set_get_labels<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    let mut value = item(1, "alice", "https://example.com");
+    value.set_category(Some(Category::Movie));
+    value.set_tags(["horror", "short"]);
+    value.set_links([
+        Url::parse("https://example.com/trailer").unwrap(),
+        Url::parse("https://example.com/review?lang=en").unwrap(),
+    ]);
+    value.set_attachment(Some(Attachment {
+        kind: AttachmentKind::Photo,
+        file_id: "AgACAgIAAxkBAAIB".to_string(),
+    }));
+    value.set_source(Some(Source {
+        chat_id: ChatId(list.0),
+        message_id: MessageId(100),
+    }));
+    value.set_forward_origin(Some(ForwardOrigin {
+        author: "Cinema Club (Alice)".to_string(),
+        chat_id: Some(ChatId(-1001234567890)),
+        message_id: Some(MessageId(42)),
+    }));
+
+    storage.set(&list, &key, value.clone()).await.unwrap();
+    assert_eq!(storage.get(&list, &key).await.unwrap(), Some(value));
+}
+
+/// Missing items are reported as `None`, not as errors
+pub(super) async fn get_missing<B: StorageBackend>(storage: B) {
+    let list = random_list();
+
+    assert_eq!(storage.get(&list, &Key::generate()).await.unwrap(), None);
+    assert!(storage.get_all(&list).await.unwrap().is_empty());
+}
+
+/// Deleted items disappear from all queries, deleting missing item is fine
+pub(super) async fn delete<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&list, &key, item(1, "alice", "https://example.com"))
+        .await
+        .unwrap();
+
+    storage.delete(&list, &key).await.unwrap();
+
+    assert_eq!(storage.get(&list, &key).await.unwrap(), None);
+    assert!(storage.get_all(&list).await.unwrap().is_empty());
+    assert!(storage
+        .get_user_items(&list, UserId(1), "alice")
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage
+        .get_random(&list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_none());
+
+    storage.delete(&list, &key).await.unwrap();
+}
+
+/// `get_all` returns only unread items, while `get` returns read ones too
+pub(super) async fn get_all_filters_read<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let unread_key = Key::generate();
+    let unread = item(1, "alice", "https://example.com/unread");
+    storage
+        .set(&list, &unread_key, unread.clone())
+        .await
+        .unwrap();
+
+    let read_key = Key::generate();
+    let mut read = item(1, "alice", "https://example.com/read");
+    read.set_read(OffsetDateTime::UNIX_EPOCH);
+    storage.set(&list, &read_key, read.clone()).await.unwrap();
+
+    assert_eq!(
+        storage.get_all(&list).await.unwrap(),
+        [(unread_key, unread)].into_iter().collect()
+    );
+    assert_eq!(storage.get(&list, &read_key).await.unwrap(), Some(read));
+}
+
+/// `get_user_items` returns unread items of the author, matching legacy ones by username
+pub(super) async fn get_user_items<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let alice_key = Key::generate();
+    let alice_item = item(1, "alice", "https://example.com/alice");
+    storage
+        .set(&list, &alice_key, alice_item.clone())
+        .await
+        .unwrap();
+
+    let legacy_key = Key::generate();
+    let legacy_item = ContentItem::legacy("alice", "https://example.com/legacy");
+    storage
+        .set(&list, &legacy_key, legacy_item.clone())
+        .await
+        .unwrap();
+
+    let bob_key = Key::generate();
+    let bob_item = item(2, "bob", "https://example.com/bob");
+    storage
+        .set(&list, &bob_key, bob_item.clone())
+        .await
+        .unwrap();
+
+    let mut read = item(1, "alice", "https://example.com/read");
+    read.set_read(OffsetDateTime::UNIX_EPOCH);
+    storage.set(&list, &Key::generate(), read).await.unwrap();
+
+    assert_eq!(
+        storage
+            .get_user_items(&list, UserId(1), "alice")
+            .await
+            .unwrap(),
+        [(alice_key, alice_item), (legacy_key, legacy_item)]
+            .into_iter()
+            .collect()
+    );
+    // renamed user still gets own items
+    assert_eq!(
+        storage
+            .get_user_items(&list, UserId(2), "bob_renamed")
+            .await
+            .unwrap(),
+        [(bob_key, bob_item)].into_iter().collect()
+    );
+}
+
+/// `get_random` returns only unread items, and every one of them eventually
+pub(super) async fn get_random<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let keys = [Key::generate(), Key::generate()];
+    for key in &keys {
+        storage
+            .set(&list, key, item(1, "alice", key.as_ref()))
+            .await
+            .unwrap();
+    }
+
+    let mut read = item(1, "alice", "https://example.com/read");
+    read.set_read(OffsetDateTime::UNIX_EPOCH);
+    storage.set(&list, &Key::generate(), read).await.unwrap();
+
+    let mut seen = std::collections::HashSet::new();
+    for _ in 0..100 {
+        let (key, item) = storage
+            .get_random(&list, &RandomOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(keys.contains(&key));
+        assert!(!item.is_read());
+        seen.insert(key);
+    }
+    assert_eq!(seen.len(), keys.len());
+}
+
+/// `get_random` returns `None` when there are no unread items
+pub(super) async fn get_random_empty<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    assert!(storage
+        .get_random(&list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_none());
+
+    let mut read = item(1, "alice", "https://example.com/read");
+    read.set_read(OffsetDateTime::UNIX_EPOCH);
+    storage.set(&list, &Key::generate(), read).await.unwrap();
+
+    assert!(storage
+        .get_random(&list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// `get_random` respects filters of the options, `mark_as_shown` is kept for `no_repeat`
+pub(super) async fn get_random_filtered<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let (alice, bob) = (Key::generate(), Key::generate());
+    storage
+        .set(&list, &alice, item(1, "alice", "https://example.com/alice"))
+        .await
+        .unwrap();
+    storage
+        .set(&list, &bob, item(2, "bob", "https://example.com/bob"))
+        .await
+        .unwrap();
+
+    async fn pick<B: StorageBackend>(
+        storage: &B,
+        list: &ListId,
+        options: RandomOptions,
+    ) -> Option<Key> {
+        storage
+            .get_random(list, &options)
+            .await
+            .unwrap()
+            .map(|(key, _)| key)
+    }
+
+    let by_bob = RandomOptions {
+        author: Some(AuthorFilter::Username("bob".to_string())),
+        ..Default::default()
+    };
+    for _ in 0..10 {
+        assert_eq!(
+            pick(&storage, &list, by_bob.clone()).await,
+            Some(bob.clone())
+        );
+    }
+    let not_alice = RandomOptions {
+        exclude: [alice.clone()].into(),
+        ..Default::default()
+    };
+    assert_eq!(pick(&storage, &list, not_alice).await, Some(bob.clone()));
+
+    storage.mark_as_shown(&list, &alice).await.unwrap();
+    let shown = storage.get(&list, &alice).await.unwrap().unwrap();
+    assert!(shown.shown_at().is_some());
+
+    let no_repeat = RandomOptions {
+        no_repeat: Some(time::Duration::days(1)),
+        ..Default::default()
+    };
+    for _ in 0..10 {
+        assert_eq!(
+            pick(&storage, &list, no_repeat.clone()).await,
+            Some(bob.clone())
+        );
+    }
+
+    // items are added at the epoch, so they're too old
+    let fresh = RandomOptions {
+        max_age: Some(time::Duration::days(1)),
+        ..Default::default()
+    };
+    assert_eq!(pick(&storage, &list, fresh).await, None);
+}
+
+/// Snoozed items stay in the queue, but `get_random` skips them until the snooze expires
+pub(super) async fn snooze<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let (snoozed, other) = (Key::generate(), Key::generate());
+    storage
+        .set(
+            &list,
+            &snoozed,
+            item(1, "alice", "https://example.com/snoozed"),
+        )
+        .await
+        .unwrap();
+
+    let now = storage.get_now().await.unwrap();
+    let updated = storage
+        .snooze(&list, &snoozed, now + time::Duration::days(7))
+        .await
+        .unwrap();
+    assert!(updated.is_snoozed(now));
+    assert!(storage.get_all(&list).await.unwrap().contains_key(&snoozed));
+    assert!(storage
+        .get_random(&list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_none());
+
+    storage
+        .set(&list, &other, item(2, "bob", "https://example.com/other"))
+        .await
+        .unwrap();
+    for _ in 0..10 {
+        let (key, _) = storage
+            .get_random(&list, &RandomOptions::default())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(key, other);
+    }
+
+    // expired snoozes don't count
+    storage
+        .snooze(&list, &other, now - time::Duration::days(1))
+        .await
+        .unwrap();
+    assert!(storage
+        .get_random(&list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_some());
+
+    let updated = storage.unsnooze(&list, &snoozed).await.unwrap();
+    assert_eq!(updated.snoozed_until(), None);
+    assert_eq!(
+        storage
+            .get(&list, &snoozed)
+            .await
+            .unwrap()
+            .unwrap()
+            .snoozed_until(),
+        None
+    );
+}
+
+/// `mark_as_read` records read time and removes item from the queue
+pub(super) async fn mark_as_read<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&list, &key, item(1, "alice", "https://example.com"))
+        .await
+        .unwrap();
+
+    let updated = storage.mark_as_read(&list, &key).await.unwrap();
+    assert!(updated.is_read());
+
+    let item = storage.get(&list, &key).await.unwrap().unwrap();
+    assert!(item.is_read());
+    assert!(storage.get_all(&list).await.unwrap().is_empty());
+    assert!(storage
+        .get_user_items(&list, UserId(1), "alice")
+        .await
+        .unwrap()
+        .is_empty());
+
+    // repeated marking keeps the original read time
+    let repeated = storage.mark_as_read(&list, &key).await.unwrap();
+    assert_eq!(repeated.read_at(), item.read_at());
+
+    assert!(storage.mark_as_read(&list, &Key::generate()).await.is_err());
+}
+
+/// `mark_as_unread` returns read item to the queue
+pub(super) async fn mark_as_unread<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&list, &key, item(1, "alice", "https://example.com"))
+        .await
+        .unwrap();
+    storage.mark_as_read(&list, &key).await.unwrap();
+
+    let updated = storage.mark_as_unread(&list, &key).await.unwrap();
+    assert!(!updated.is_read());
+
+    let item = storage.get(&list, &key).await.unwrap().unwrap();
+    assert!(!item.is_read());
+    assert!(storage.get_all(&list).await.unwrap().contains_key(&key));
+    assert!(storage
+        .get_history(&list, &HistoryFilter::default())
+        .await
+        .unwrap()
+        .is_empty());
+
+    // repeated undo is harmless
+    storage.mark_as_unread(&list, &key).await.unwrap();
+    assert_eq!(storage.get_all(&list).await.unwrap().len(), 1);
+
+    assert!(storage
+        .mark_as_unread(&list, &Key::generate())
+        .await
+        .is_err());
+}
+
+/// `update` modifies existing items and ignores missing ones
+pub(super) async fn update<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&list, &key, item(1, "alice", "https://example.com"))
+        .await
+        .unwrap();
+
+    let updated = storage
+        .update(&list, &key, |item| {
+            item.set_read(OffsetDateTime::UNIX_EPOCH)
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(updated.is_read());
+    assert_eq!(storage.get(&list, &key).await.unwrap(), Some(updated));
+    assert!(storage.get_all(&list).await.unwrap().is_empty());
+
+    assert_eq!(
+        storage
+            .update(&list, &Key::generate(), |item| item.set_unread())
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+/// Concurrent updates don't overwrite each other
+pub(super) async fn concurrent_updates<B: StorageBackend + 'static>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&list, &key, item(1, "alice", ""))
+        .await
+        .unwrap();
+
+    let tasks: Vec<_> = (0..10)
+        .map(|_| {
+            let mut storage = storage.clone();
+            let key = key.clone();
+
+            tokio::spawn(async move {
+                storage
+                    .update(&list, &key, |item| {
+                        item.set_content(format!("{}+", item.content()))
+                    })
+                    .await
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+
+    let item = storage.get(&list, &key).await.unwrap().unwrap();
+    assert_eq!(item.content(), "+".repeat(10));
+}
+
+/// Lists don't leak into each other
+pub(super) async fn lists_are_isolated<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let other_list = random_list();
+    let key = Key::generate();
+    let item = item(1, "alice", "https://example.com");
+    storage.set(&list, &key, item.clone()).await.unwrap();
+
+    assert_eq!(storage.get(&other_list, &key).await.unwrap(), None);
+    assert!(storage.get_all(&other_list).await.unwrap().is_empty());
+    assert!(storage
+        .get_user_items(&other_list, UserId(1), "alice")
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage
+        .get_random(&other_list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_none());
+
+    storage.delete(&other_list, &key).await.unwrap();
+    assert_eq!(storage.get(&list, &key).await.unwrap(), Some(item));
+}
+
+/// `dump` returns every item of the list, read ones included
+pub(super) async fn dump<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    assert!(storage.dump(&list).await.unwrap().is_empty());
+
+    let unread_key = Key::generate();
+    let unread = item(1, "alice", "https://example.com/unread");
+    storage
+        .set(&list, &unread_key, unread.clone())
+        .await
+        .unwrap();
+
+    let read_key = Key::generate();
+    let mut read = item(2, "bob", "https://example.com/read");
+    read.set_read(OffsetDateTime::UNIX_EPOCH);
+    storage.set(&list, &read_key, read.clone()).await.unwrap();
+
+    let legacy_key = Key::generate();
+    let legacy = ContentItem::legacy("alice", "https://example.com/legacy");
+    storage
+        .set(&list, &legacy_key, legacy.clone())
+        .await
+        .unwrap();
+
+    storage
+        .set(&random_list(), &Key::generate(), item(1, "alice", "other"))
+        .await
+        .unwrap();
+
+    assert_eq!(
+        storage.dump(&list).await.unwrap(),
+        [(unread_key, unread), (read_key, read), (legacy_key, legacy)]
+            .into_iter()
+            .collect()
+    );
+}
+
+/// `get_lists` reports lists with any items or saved settings, and only them
+pub(super) async fn get_lists<B: StorageBackend>(mut storage: B) {
+    let unread_list = random_list();
+    storage
+        .set(&unread_list, &Key::generate(), item(1, "alice", "unread"))
+        .await
+        .unwrap();
+
+    let read_list = random_list();
+    let mut read = item(1, "alice", "read");
+    read.set_read(OffsetDateTime::UNIX_EPOCH);
+    storage
+        .set(&read_list, &Key::generate(), read)
+        .await
+        .unwrap();
+
+    let emptied_list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&emptied_list, &key, item(1, "alice", "deleted"))
+        .await
+        .unwrap();
+    storage.delete(&emptied_list, &key).await.unwrap();
+
+    let settings_list = random_list();
+    let mut settings = ChatSettings::default();
+    settings.random.no_repeat_days = Some(7);
+    storage
+        .set_settings(&settings_list, &settings)
+        .await
+        .unwrap();
+
+    // backends with shared state may have lists of other tests as well
+    let lists = storage.get_lists().await.unwrap();
+    assert!(lists.contains(&unread_list));
+    assert!(lists.contains(&read_list));
+    assert!(lists.contains(&settings_list));
+    assert!(!lists.contains(&emptied_list));
+}
+
+/// Archived items leave the queue, but stay in the archive until restored
+pub(super) async fn archive<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let key = Key::generate();
+    storage
+        .set(&list, &key, item(1, "alice", "https://example.com"))
+        .await
+        .unwrap();
+
+    let archived = storage.archive(&list, &key).await.unwrap();
+    assert_eq!(
+        storage.get(&list, &key).await.unwrap(),
+        Some(archived.clone())
+    );
+    assert!(archived.is_archived());
+    assert!(!archived.is_read());
+    assert!(storage.get_all(&list).await.unwrap().is_empty());
+    assert!(storage
+        .get_user_items(&list, UserId(1), "alice")
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(storage
+        .get_random(&list, &RandomOptions::default())
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        storage.get_archived(&list).await.unwrap(),
+        [(key.clone(), archived.clone())].into_iter().collect()
+    );
+    assert_eq!(storage.dump(&list).await.unwrap().len(), 1);
+
+    let restored = storage.restore(&list, &key).await.unwrap();
+    assert_eq!(
+        storage.get(&list, &key).await.unwrap(),
+        Some(restored.clone())
+    );
+    assert!(!restored.is_archived());
+    assert!(storage.get_archived(&list).await.unwrap().is_empty());
+    assert_eq!(
+        storage.get_all(&list).await.unwrap(),
+        [(key, restored)].into_iter().collect()
+    );
+
+    assert!(storage.archive(&list, &Key::generate()).await.is_err());
+}
+
+/// `get_history` returns read items in the time range, oldest first
+pub(super) async fn history<B: StorageBackend>(mut storage: B) {
+    let list = random_list();
+    let at = |seconds| OffsetDateTime::from_unix_timestamp(seconds).unwrap();
+
+    let mut expected = Vec::new();
+    for (author_id, author, read_at) in [
+        (1, "alice", at(3000)),
+        (2, "bob", at(1000)),
+        // stored with a different offset, but it's still between the other two
+        (
+            1,
+            "alice",
+            at(2000).to_offset(time::UtcOffset::from_hms(3, 0, 0).unwrap()),
+        ),
+    ] {
+        let key = Key::generate();
+        let mut item = item(author_id, author, "https://example.com");
+        item.set_read(read_at);
+        storage.set(&list, &key, item.clone()).await.unwrap();
+        expected.push((key, item));
+    }
+    expected.sort_by_key(|(_, item)| item.read_at());
+
+    let legacy_key = Key::generate();
+    let mut legacy = ContentItem::legacy("alice", "https://example.com/legacy");
+    legacy.set_read(at(4000));
+    storage
+        .set(&list, &legacy_key, legacy.clone())
+        .await
+        .unwrap();
+    expected.push((legacy_key, legacy));
+
+    // neither unread nor archived items are part of the history
+    storage
+        .set(&list, &Key::generate(), item(1, "alice", "unread"))
+        .await
+        .unwrap();
+    let mut archived = item(1, "alice", "archived");
+    archived.set_read(at(2500));
+    archived.archive(at(2600));
+    storage
+        .set(&list, &Key::generate(), archived)
+        .await
+        .unwrap();
+
+    let history = storage
+        .get_history(&list, &HistoryFilter::default())
+        .await
+        .unwrap();
+    assert_eq!(history, expected);
+
+    let range = HistoryFilter {
+        from: Some(at(2000)),
+        to: Some(at(4000)),
+        ..Default::default()
+    };
+    assert_eq!(
+        storage.get_history(&list, &range).await.unwrap(),
+        expected[1..3]
+    );
+
+    let alice = HistoryFilter {
+        author: Some((UserId(1), "alice".to_string())),
+        ..Default::default()
+    };
+    assert_eq!(
+        storage.get_history(&list, &alice).await.unwrap(),
+        [&expected[1..3], &expected[3..]].concat()
+    );
+
+    assert!(storage
+        .get_history(&random_list(), &HistoryFilter::default())
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+/// Settings are stored per list, with defaults for lists without them
+pub(super) async fn settings<B: StorageBackend>(mut storage: B) {
+    let (list, other_list) = (random_list(), random_list());
+    assert_eq!(
+        storage.get_settings(&list).await.unwrap(),
+        ChatSettings::default()
+    );
+
+    let mut settings = ChatSettings::default();
+    settings.random.no_repeat_days = Some(7);
+    storage.set_settings(&list, &settings).await.unwrap();
+    assert_eq!(storage.get_settings(&list).await.unwrap(), settings);
+    assert_eq!(
+        storage.get_settings(&other_list).await.unwrap(),
+        ChatSettings::default()
+    );
+
+    // overwriting
+    settings.random.no_repeat_days = None;
+    storage.set_settings(&list, &settings).await.unwrap();
+    assert_eq!(storage.get_settings(&list).await.unwrap(), settings);
+}
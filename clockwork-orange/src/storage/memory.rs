@@ -2,17 +2,21 @@
 //!
 //! This storage is used for testing purposes only.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
 use color_eyre::Result;
 use tokio::sync::{Mutex, MutexGuard};
 
-use super::{ContentItem, Key, StorageBackend};
+use super::{ContentItem, Key, ListId, StorageBackend};
+use crate::settings::ChatSettings;
+
+type Lists = HashMap<ListId, HashMap<Key, ContentItem>>;
 
 #[derive(Debug, Default, Clone)]
 pub struct MemoryStorage {
-    items: Arc<Mutex<HashMap<Key, ContentItem>>>,
+    lists: Arc<Mutex<Lists>>,
+    settings: Arc<Mutex<HashMap<ListId, ChatSettings>>>,
 }
 
 impl MemoryStorage {
@@ -20,8 +24,8 @@ impl MemoryStorage {
         Default::default()
     }
 
-    pub async fn items(&self) -> MutexGuard<HashMap<Key, ContentItem>> {
-        self.items.lock().await
+    pub async fn lists(&self) -> MutexGuard<'_, Lists> {
+        self.lists.lock().await
     }
 
     pub fn into_storage(self) -> super::Storage<Self> {
@@ -31,31 +35,90 @@ impl MemoryStorage {
 
 #[async_trait::async_trait]
 impl StorageBackend for MemoryStorage {
-    async fn get(&self, key: &Key) -> Result<Option<ContentItem>> {
-        Ok(self.items().await.get(key).cloned())
+    async fn get(&self, list: &ListId, key: &Key) -> Result<Option<ContentItem>> {
+        Ok(self
+            .lists()
+            .await
+            .get(list)
+            .and_then(|items| items.get(key))
+            .cloned())
     }
 
-    async fn set(&mut self, key: &Key, value: ContentItem) -> Result<()> {
-        self.items().await.insert(key.clone(), value);
+    async fn set(&mut self, list: &ListId, key: &Key, value: ContentItem) -> Result<()> {
+        self.lists()
+            .await
+            .entry(*list)
+            .or_default()
+            .insert(key.clone(), value);
         Ok(())
     }
 
-    async fn get_all(&self) -> Result<HashMap<Key, ContentItem>> {
+    async fn update<F>(&mut self, list: &ListId, key: &Key, mut f: F) -> Result<Option<ContentItem>>
+    where
+        F: FnMut(&mut ContentItem) + Send,
+    {
+        let mut lists = self.lists().await;
+        let Some(item) = lists.get_mut(list).and_then(|items| items.get_mut(key)) else {
+            return Ok(None);
+        };
+
+        f(item);
+
+        Ok(Some(item.clone()))
+    }
+
+    async fn get_all(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
         Ok(self
-            .items()
+            .lists()
             .await
-            .iter()
-            .filter(|(_, item)| !item.is_read())
+            .get(list)
+            .into_iter()
+            .flatten()
+            .filter(|(_, item)| item.is_queued())
             .map(|(key, item)| (key.clone(), item.clone()))
             .collect())
     }
 
+    async fn dump(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        Ok(self.lists().await.get(list).cloned().unwrap_or_default())
+    }
+
+    async fn get_lists(&self) -> Result<Vec<ListId>> {
+        let mut lists: HashSet<ListId> = self
+            .lists()
+            .await
+            .iter()
+            .filter(|(_, items)| !items.is_empty())
+            .map(|(list, _)| *list)
+            .collect();
+        lists.extend(self.settings.lock().await.keys());
+
+        Ok(lists.into_iter().collect())
+    }
+
     async fn get_now(&self) -> Result<time::OffsetDateTime> {
         Ok(time::OffsetDateTime::now_utc())
     }
 
-    async fn delete(&mut self, key: &Key) -> Result<()> {
-        self.items().await.remove(key);
+    async fn delete(&mut self, list: &ListId, key: &Key) -> Result<()> {
+        if let Some(items) = self.lists().await.get_mut(list) {
+            items.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn get_settings(&self, list: &ListId) -> Result<ChatSettings> {
+        Ok(self
+            .settings
+            .lock()
+            .await
+            .get(list)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_settings(&mut self, list: &ListId, settings: &ChatSettings) -> Result<()> {
+        self.settings.lock().await.insert(*list, settings.clone());
         Ok(())
     }
 
@@ -66,18 +129,30 @@ impl StorageBackend for MemoryStorage {
 
 #[cfg(test)]
 mod tests {
+    use teloxide::types::UserId;
+    use time::OffsetDateTime;
+
     use super::*;
 
+    use crate::storage::conformance::conformance_tests;
+
+    const LIST: ListId = ListId(1);
+
     #[tokio::test]
     /// Test basic set/get functionality
     async fn test_simple() {
         let mut storage = MemoryStorage::new();
         let key = Key("test".to_string());
-        let item = ContentItem::new("test".to_string(), "https://example.com");
+        let item = ContentItem::new(
+            UserId(1),
+            "test",
+            "https://example.com",
+            OffsetDateTime::UNIX_EPOCH,
+        );
 
-        storage.set(&key, item.clone()).await.unwrap();
+        storage.set(&LIST, &key, item.clone()).await.unwrap();
 
-        assert_eq!(storage.get(&key).await.unwrap(), Some(item));
+        assert_eq!(storage.get(&LIST, &key).await.unwrap(), Some(item));
     }
 
     #[tokio::test]
@@ -85,15 +160,20 @@ mod tests {
     async fn test_get_all() {
         let mut storage = MemoryStorage::new();
         let key = Key("test".to_string());
-        let item = ContentItem::new("test".to_string(), "https://example.com");
+        let item = ContentItem::new(
+            UserId(1),
+            "test",
+            "https://example.com",
+            OffsetDateTime::UNIX_EPOCH,
+        );
 
-        storage.set(&key, item).await.unwrap();
+        storage.set(&LIST, &key, item).await.unwrap();
 
-        assert!(!storage.get_all().await.unwrap().is_empty());
+        assert!(!storage.get_all(&LIST).await.unwrap().is_empty());
 
-        storage.mark_as_read(&key).await.unwrap();
+        storage.mark_as_read(&LIST, &key).await.unwrap();
 
-        assert!(storage.get_all().await.unwrap().is_empty());
+        assert!(storage.get_all(&LIST).await.unwrap().is_empty());
     }
 
     #[tokio::test]
@@ -101,25 +181,49 @@ mod tests {
     async fn test_get_user_items() {
         let mut storage = MemoryStorage::new();
         let alice_key = Key("alice_data".to_string());
-        let alice_item = ContentItem::new("alice".to_string(), "https://example.com/alice");
-        storage.set(&alice_key, alice_item.clone()).await.unwrap();
+        let alice_item = ContentItem::new(
+            UserId(1),
+            "alice",
+            "https://example.com/alice",
+            OffsetDateTime::UNIX_EPOCH,
+        );
+        storage
+            .set(&LIST, &alice_key, alice_item.clone())
+            .await
+            .unwrap();
 
         let bob_key = Key("bob_data".to_string());
-        let bob_item = ContentItem::new("bob".to_string(), "https://example.com/bob");
-        storage.set(&bob_key, bob_item.clone()).await.unwrap();
+        let bob_item = ContentItem::new(
+            UserId(2),
+            "bob",
+            "https://example.com/bob",
+            OffsetDateTime::UNIX_EPOCH,
+        );
+        storage
+            .set(&LIST, &bob_key, bob_item.clone())
+            .await
+            .unwrap();
 
         assert_eq!(
-            storage.get_user_items("alice").await.unwrap(),
+            storage
+                .get_user_items(&LIST, UserId(1), "alice")
+                .await
+                .unwrap(),
             vec![(alice_key.clone(), alice_item.clone())]
                 .into_iter()
                 .collect()
         );
 
         assert_eq!(
-            storage.get_user_items("bob").await.unwrap(),
+            storage
+                .get_user_items(&LIST, UserId(2), "bob")
+                .await
+                .unwrap(),
             vec![(bob_key.clone(), bob_item.clone())]
                 .into_iter()
                 .collect()
         );
     }
+
+    conformance_tests!(MemoryStorage::new());
 }
@@ -0,0 +1,493 @@
+//! Postgres storage backend.
+//!
+//! Schema is versioned in `migrations/postgres` and applied on startup,
+//! all the filtering is done by Postgres itself.
+//!
+//! Tests require a live database, so they're ignored by default. To run them:
+//! `TEST_DATABASE_URL=postgres://... cargo test -- --ignored`
+
+use std::collections::HashMap;
+
+use color_eyre::{eyre::WrapErr, Result};
+use sqlx::{
+    postgres::{PgPool, PgPoolOptions, PgRow},
+    PgExecutor, Row,
+};
+use teloxide::types::{ChatId, MessageId, UserId};
+use time::OffsetDateTime;
+use url::Url;
+
+use super::{random, ContentItem, HistoryFilter, Key, ListId, RandomOptions, StorageBackend};
+use crate::{
+    content_item::{Attachment, Category, ForwardOrigin, Source},
+    settings::ChatSettings,
+};
+
+#[derive(Debug, Clone)]
+pub struct PostgresStorage {
+    pool: PgPool,
+}
+
+impl PostgresStorage {
+    /// Connect to the database and apply pending migrations
+    pub async fn new(url: &str) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .connect(url)
+            .await
+            .wrap_err("failed to connect to Postgres")?;
+
+        sqlx::migrate!("migrations/postgres")
+            .run(&pool)
+            .await
+            .wrap_err("failed to run Postgres migrations")?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn into_storage(self) -> super::Storage<Self> {
+        super::Storage { backend: self }
+    }
+}
+
+/// Columns of the `items` table read by `from_row`
+const ITEM_COLUMNS: &str =
+    "key, author_id, author, content, added_at, read_at, archived_at, shown_at, snoozed_until, \
+    category, tags, links, attachment_kind, attachment_file_id, source_chat_id, source_message_id, \
+    forward_author, forward_chat_id, forward_message_id";
+
+/// Convert `items` table row to (key, item) pair
+fn from_row(row: PgRow) -> Result<(Key, ContentItem)> {
+    let key: String = row.try_get("key")?;
+    let author_id: Option<i64> = row.try_get("author_id")?;
+    let author: String = row.try_get("author")?;
+    let content: String = row.try_get("content")?;
+    let added_at: Option<OffsetDateTime> = row.try_get("added_at")?;
+    let read_at: Option<OffsetDateTime> = row.try_get("read_at")?;
+    let archived_at: Option<OffsetDateTime> = row.try_get("archived_at")?;
+    let shown_at: Option<OffsetDateTime> = row.try_get("shown_at")?;
+    let snoozed_until: Option<OffsetDateTime> = row.try_get("snoozed_until")?;
+    let category: Option<String> = row.try_get("category")?;
+    let tags: String = row.try_get("tags")?;
+    let links: String = row.try_get("links")?;
+    let attachment_kind: Option<String> = row.try_get("attachment_kind")?;
+    let attachment_file_id: Option<String> = row.try_get("attachment_file_id")?;
+    let source_chat_id: Option<i64> = row.try_get("source_chat_id")?;
+    let source_message_id: Option<i32> = row.try_get("source_message_id")?;
+    let forward_author: Option<String> = row.try_get("forward_author")?;
+    let forward_chat_id: Option<i64> = row.try_get("forward_chat_id")?;
+    let forward_message_id: Option<i32> = row.try_get("forward_message_id")?;
+
+    let mut item = match (author_id, added_at) {
+        (Some(author_id), Some(added_at)) => {
+            ContentItem::new(UserId(author_id as u64), author, content, added_at)
+        }
+        _ => ContentItem::legacy(author, content),
+    };
+    if let Some(read_at) = read_at {
+        item.set_read(read_at);
+    }
+    if let Some(archived_at) = archived_at {
+        item.archive(archived_at);
+    }
+    if let Some(shown_at) = shown_at {
+        item.set_shown(shown_at);
+    }
+    if let Some(snoozed_until) = snoozed_until {
+        item.snooze(snoozed_until);
+    }
+    item.set_category(category.as_deref().map(str::parse).transpose()?);
+    item.set_tags(tags.split_whitespace());
+    item.set_links(
+        links
+            .split_whitespace()
+            .map(Url::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+    if let (Some(kind), Some(file_id)) = (attachment_kind, attachment_file_id) {
+        item.set_attachment(Some(Attachment {
+            kind: kind.parse()?,
+            file_id,
+        }));
+    }
+    if let (Some(chat_id), Some(message_id)) = (source_chat_id, source_message_id) {
+        item.set_source(Some(Source {
+            chat_id: ChatId(chat_id),
+            message_id: MessageId(message_id),
+        }));
+    }
+    item.set_forward_origin(forward_author.map(|author| ForwardOrigin {
+        author,
+        chat_id: forward_chat_id.map(ChatId),
+        message_id: forward_message_id.map(MessageId),
+    }));
+
+    Ok((Key(key), item))
+}
+
+/// Fetch single item, either from the pool or inside a transaction.
+///
+/// With `lock` the row stays locked until the end of the transaction.
+async fn fetch_item<'e, E: PgExecutor<'e>>(
+    executor: E,
+    list: &ListId,
+    key: &Key,
+    lock: bool,
+) -> Result<Option<ContentItem>> {
+    let mut query = format!(
+        "SELECT {ITEM_COLUMNS} FROM items
+        WHERE list_id = $1 AND key = $2",
+    );
+    if lock {
+        query.push_str(" FOR UPDATE");
+    }
+
+    let row = sqlx::query(&query)
+        .bind(list.0)
+        .bind(key.as_ref())
+        .fetch_optional(executor)
+        .await
+        .wrap_err_with(|| format!("failed to get item from Postgres by key `{key:?}`"))?;
+
+    Ok(row.map(from_row).transpose()?.map(|(_, item)| item))
+}
+
+/// Insert or replace single item, either through the pool or inside a transaction
+async fn store_item<'e, E: PgExecutor<'e>>(
+    executor: E,
+    list: &ListId,
+    key: &Key,
+    value: &ContentItem,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO items (
+            list_id, key, author_id, author, content, added_at, read_at, archived_at, shown_at,
+            snoozed_until, category, tags, links, attachment_kind, attachment_file_id,
+            source_chat_id, source_message_id, forward_author, forward_chat_id, forward_message_id
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+        ON CONFLICT (list_id, key) DO UPDATE SET
+            author_id = excluded.author_id,
+            author = excluded.author,
+            content = excluded.content,
+            added_at = excluded.added_at,
+            read_at = excluded.read_at,
+            archived_at = excluded.archived_at,
+            shown_at = excluded.shown_at,
+            snoozed_until = excluded.snoozed_until,
+            category = excluded.category,
+            tags = excluded.tags,
+            links = excluded.links,
+            attachment_kind = excluded.attachment_kind,
+            attachment_file_id = excluded.attachment_file_id,
+            source_chat_id = excluded.source_chat_id,
+            source_message_id = excluded.source_message_id,
+            forward_author = excluded.forward_author,
+            forward_chat_id = excluded.forward_chat_id,
+            forward_message_id = excluded.forward_message_id",
+    )
+    .bind(list.0)
+    .bind(key.as_ref())
+    .bind(value.author_id().map(|author_id| author_id.0 as i64))
+    .bind(value.author())
+    .bind(value.content())
+    .bind(value.added_at())
+    .bind(value.read_at())
+    .bind(value.archived_at())
+    .bind(value.shown_at())
+    .bind(value.snoozed_until())
+    .bind(value.category().map(Category::as_str))
+    .bind(value.tags().join(" "))
+    .bind(
+        value
+            .links()
+            .iter()
+            .map(Url::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+    .bind(
+        value
+            .attachment()
+            .map(|attachment| attachment.kind.as_str()),
+    )
+    .bind(
+        value
+            .attachment()
+            .map(|attachment| attachment.file_id.as_str()),
+    )
+    .bind(value.source().map(|source| source.chat_id.0))
+    .bind(value.source().map(|source| source.message_id.0))
+    .bind(value.forward_origin().map(|origin| origin.author.as_str()))
+    .bind(value.forward_origin().and_then(|origin| origin.chat_id).map(|chat_id| chat_id.0))
+    .bind(
+        value
+            .forward_origin()
+            .and_then(|origin| origin.message_id)
+            .map(|message_id| message_id.0),
+    )
+    .execute(executor)
+    .await
+    .wrap_err("failed to set item via Postgres")?;
+
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for PostgresStorage {
+    #[tracing::instrument(err, skip(self))]
+    async fn get(&self, list: &ListId, key: &Key) -> Result<Option<ContentItem>> {
+        fetch_item(&self.pool, list, key, false).await
+    }
+
+    #[tracing::instrument(err, skip(self, value))]
+    async fn set(&mut self, list: &ListId, key: &Key, value: ContentItem) -> Result<()> {
+        store_item(&self.pool, list, key, &value).await
+    }
+
+    #[tracing::instrument(err, skip(self, f))]
+    async fn update<F>(&mut self, list: &ListId, key: &Key, mut f: F) -> Result<Option<ContentItem>>
+    where
+        F: FnMut(&mut ContentItem) + Send,
+    {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .wrap_err("failed to begin Postgres transaction")?;
+
+        let Some(mut item) = fetch_item(&mut *transaction, list, key, true).await? else {
+            return Ok(None);
+        };
+
+        f(&mut item);
+        store_item(&mut *transaction, list, key, &item).await?;
+
+        transaction
+            .commit()
+            .await
+            .wrap_err("failed to commit Postgres transaction")?;
+
+        Ok(Some(item))
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_all(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = $1 AND read_at IS NULL AND archived_at IS NULL",
+        ))
+        .bind(list.0)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get items from Postgres")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn dump(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = $1",
+        ))
+        .bind(list.0)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to dump items from Postgres")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_lists(&self) -> Result<Vec<ListId>> {
+        let lists: Vec<i64> =
+            sqlx::query_scalar("SELECT list_id FROM items UNION SELECT list_id FROM chat_settings")
+                .fetch_all(&self.pool)
+                .await
+                .wrap_err("failed to get lists from Postgres")?;
+
+        Ok(lists.into_iter().map(ListId).collect())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_archived(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = $1 AND archived_at IS NOT NULL",
+        ))
+        .bind(list.0)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get archived items from Postgres")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_history(
+        &self,
+        list: &ListId,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<(Key, ContentItem)>> {
+        let (author_id, username) = match &filter.author {
+            Some((user_id, username)) => (Some(user_id.0 as i64), Some(username.as_str())),
+            None => (None, None),
+        };
+
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = $1 AND read_at IS NOT NULL AND archived_at IS NULL
+                AND ($2::timestamptz IS NULL OR read_at >= $2)
+                AND ($3::timestamptz IS NULL OR read_at < $3)
+                AND ($4::bigint IS NULL OR author_id = $4 OR (author_id IS NULL AND author = $5))
+            ORDER BY read_at, key",
+        ))
+        .bind(list.0)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(author_id)
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get history from Postgres")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_user_items(
+        &self,
+        list: &ListId,
+        user_id: UserId,
+        username: &str,
+    ) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = $1 AND read_at IS NULL AND archived_at IS NULL
+                AND (author_id = $2 OR (author_id IS NULL AND author = $3))",
+        ))
+        .bind(list.0)
+        .bind(user_id.0 as i64)
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get user items from Postgres")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, ret, skip(self))]
+    async fn get_now(&self) -> Result<OffsetDateTime> {
+        sqlx::query_scalar("SELECT now()")
+            .fetch_one(&self.pool)
+            .await
+            .wrap_err("failed to get time from Postgres")
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn delete(&mut self, list: &ListId, key: &Key) -> Result<()> {
+        sqlx::query("DELETE FROM items WHERE list_id = $1 AND key = $2")
+            .bind(list.0)
+            .bind(key.as_ref())
+            .execute(&self.pool)
+            .await
+            .wrap_err_with(|| format!("failed to delete item by key {key:?}"))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(fields(random_key), err, skip(self))]
+    async fn get_random(
+        &self,
+        list: &ListId,
+        options: &RandomOptions,
+    ) -> Result<Option<(Key, ContentItem)>> {
+        if !options.is_uniform() {
+            return random::get_random(self, list, options).await;
+        }
+
+        let row = sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = $1 AND read_at IS NULL AND archived_at IS NULL
+                AND (snoozed_until IS NULL OR snoozed_until <= now())
+            ORDER BY random() LIMIT 1",
+        ))
+        .bind(list.0)
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("failed to get random item from Postgres")?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let (key, item) = from_row(row)?;
+        tracing::Span::current().record("random_key", &key.0);
+
+        Ok(Some((key, item)))
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_settings(&self, list: &ListId) -> Result<ChatSettings> {
+        let settings: Option<String> =
+            sqlx::query_scalar("SELECT settings FROM chat_settings WHERE list_id = $1")
+                .bind(list.0)
+                .fetch_optional(&self.pool)
+                .await
+                .wrap_err("failed to get chat settings from Postgres")?;
+
+        settings
+            .map(|settings| {
+                serde_json::from_str(&settings).wrap_err("failed to deserialize chat settings")
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_settings(&mut self, list: &ListId, settings: &ChatSettings) -> Result<()> {
+        let settings =
+            serde_json::to_string(settings).wrap_err("failed to serialize chat settings")?;
+
+        sqlx::query(
+            "INSERT INTO chat_settings (list_id, settings) VALUES ($1, $2)
+            ON CONFLICT (list_id) DO UPDATE SET settings = excluded.settings",
+        )
+        .bind(list.0)
+        .bind(settings)
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to set chat settings via Postgres")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn health_check(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to ping Postgres")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::conformance::conformance_tests;
+
+    async fn storage() -> PostgresStorage {
+        let url = std::env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL unspecified");
+
+        PostgresStorage::new(&url).await.unwrap()
+    }
+
+    conformance_tests!(storage().await, #[ignore = "requires TEST_DATABASE_URL"]);
+}
@@ -0,0 +1,318 @@
+//! Picking random items with filters and weights, see `StorageBackend::get_random`.
+//!
+//! Filters are applied to the queued items in memory, so backends only have
+//! to provide their items – lists are small enough for that.
+
+use std::collections::HashSet;
+
+use color_eyre::Result;
+use rand::{distributions::WeightedIndex, prelude::Distribution, seq::IteratorRandom};
+use serde::{Deserialize, Serialize};
+use teloxide::types::UserId;
+use time::{Duration, OffsetDateTime};
+
+use super::{ContentItem, Key, ListId, StorageBackend};
+
+/// How likely items are to be picked
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weighting {
+    /// Every item is equally likely
+    #[default]
+    Uniform,
+    /// The longer the item waits in the queue, the more likely it is
+    Older,
+    /// Items added by somebody else than the author of the last shown item go first
+    Alternate,
+}
+
+/// Author to pick items of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthorFilter {
+    /// The user themselves, username is used to find legacy items
+    User(UserId, String),
+    /// Somebody mentioned by username
+    Username(String),
+}
+
+impl AuthorFilter {
+    pub fn matches(&self, item: &ContentItem) -> bool {
+        match self {
+            Self::User(user_id, username) => item.is_added_by(*user_id, username),
+            Self::Username(username) => item.author().eq_ignore_ascii_case(username),
+        }
+    }
+}
+
+/// How to pick a random item, default options pick any queued item uniformly.
+///
+/// Snoozed items are skipped with any options.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RandomOptions {
+    pub author: Option<AuthorFilter>,
+    /// Only items with the tag or the category, see `ContentItem::matches_tag`
+    pub tag: Option<String>,
+    /// Only items added at most this long ago, legacy items without the date are skipped
+    pub max_age: Option<Duration>,
+    /// Skip items shown by `/random` this recently
+    pub no_repeat: Option<Duration>,
+    pub weighting: Weighting,
+    /// Never pick these items, e.g. ones shown earlier in the session
+    pub exclude: HashSet<Key>,
+}
+
+impl RandomOptions {
+    /// Whether any queued item can be picked with the same chance,
+    /// so backends can pick it on their side
+    pub fn is_uniform(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check whether the queued item can be picked
+    fn matches(&self, key: &Key, item: &ContentItem, now: OffsetDateTime) -> bool {
+        let is_recent = |since: Option<OffsetDateTime>, period: Duration| {
+            since.is_some_and(|since| now - since < period)
+        };
+
+        item.is_queued()
+            && !item.is_snoozed(now)
+            && !self.exclude.contains(key)
+            && self
+                .author
+                .as_ref()
+                .is_none_or(|author| author.matches(item))
+            && self.tag.as_ref().is_none_or(|tag| item.matches_tag(tag))
+            && self
+                .max_age
+                .is_none_or(|max_age| is_recent(item.added_at(), max_age))
+            && self
+                .no_repeat
+                .is_none_or(|period| !is_recent(item.shown_at(), period))
+    }
+}
+
+/// Pick random item of the list with the options – implementation
+/// of `StorageBackend::get_random` for any backend
+pub async fn get_random<B>(
+    storage: &B,
+    list: &ListId,
+    options: &RandomOptions,
+) -> Result<Option<(Key, ContentItem)>>
+where
+    B: StorageBackend,
+{
+    let now = storage.get_now().await?;
+    let items = storage.get_all(list).await?;
+
+    // read items count too – the last shown item is usually watched by now
+    let last_shown = match options.weighting {
+        Weighting::Alternate => storage
+            .dump(list)
+            .await?
+            .into_values()
+            .filter(|item| item.shown_at().is_some())
+            .max_by_key(|item| item.shown_at()),
+        Weighting::Uniform | Weighting::Older => None,
+    };
+
+    let candidates: Vec<_> = items
+        .into_iter()
+        .filter(|(key, item)| options.matches(key, item, now))
+        .collect();
+
+    Ok(pick(
+        candidates,
+        options.weighting,
+        last_shown.as_ref(),
+        now,
+    ))
+}
+
+/// Pick one of the candidates according to the weighting
+fn pick(
+    mut candidates: Vec<(Key, ContentItem)>,
+    weighting: Weighting,
+    last_shown: Option<&ContentItem>,
+    now: OffsetDateTime,
+) -> Option<(Key, ContentItem)> {
+    let mut rng = rand::thread_rng();
+
+    let index = match weighting {
+        Weighting::Uniform => (0..candidates.len()).choose(&mut rng)?,
+        Weighting::Older => {
+            let weights = age_weights(&candidates, now);
+            WeightedIndex::new(weights).ok()?.sample(&mut rng)
+        }
+        Weighting::Alternate => {
+            let others: Vec<_> = candidates
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, item))| {
+                    last_shown.is_none_or(|last_shown| !item.has_same_author(last_shown))
+                })
+                .map(|(index, _)| index)
+                .collect();
+
+            // only the same author's items are left, so alternating is impossible
+            if others.is_empty() {
+                (0..candidates.len()).choose(&mut rng)?
+            } else {
+                *others.iter().choose(&mut rng)?
+            }
+        }
+    };
+
+    Some(candidates.swap_remove(index))
+}
+
+/// Weights proportional to the days the items wait in the queue.
+///
+/// Legacy items without the date are considered as old as the oldest item,
+/// and every item gets at least a day, so fresh ones still have a chance.
+fn age_weights(candidates: &[(Key, ContentItem)], now: OffsetDateTime) -> Vec<f64> {
+    let days = |item: &ContentItem| {
+        item.added_at()
+            .map(|added_at| (now - added_at).as_seconds_f64() / 86400.0)
+    };
+    let oldest = candidates
+        .iter()
+        .filter_map(|(_, item)| days(item))
+        .fold(0.0, f64::max);
+
+    candidates
+        .iter()
+        .map(|(_, item)| days(item).unwrap_or(oldest).max(0.0) + 1.0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn now() -> OffsetDateTime {
+        OffsetDateTime::from_unix_timestamp(1_700_000_000).unwrap()
+    }
+
+    fn item(author: u64, days_ago: i64) -> (Key, ContentItem) {
+        let item = ContentItem::new(
+            UserId(author),
+            format!("user{author}"),
+            "https://example.com",
+            now() - Duration::days(days_ago),
+        );
+
+        (Key::generate(), item)
+    }
+
+    /// Pick an item many times, counting picks of every key
+    fn count_picks(
+        candidates: &[(Key, ContentItem)],
+        weighting: Weighting,
+        last_shown: Option<&ContentItem>,
+        now: OffsetDateTime,
+    ) -> HashMap<Key, usize> {
+        let mut counts = HashMap::new();
+        for _ in 0..1000 {
+            let (key, _) = pick(candidates.to_vec(), weighting, last_shown, now).unwrap();
+            *counts.entry(key).or_default() += 1;
+        }
+
+        counts
+    }
+
+    #[test]
+    /// Test that options filter by author, tag, age, recent showing and exclusions
+    fn test_matches() {
+        let (key, mut item) = item(1, 10);
+        let options = |options: RandomOptions| options.matches(&key, &item, now());
+
+        assert!(options(RandomOptions::default()));
+        assert!(options(RandomOptions {
+            author: Some(AuthorFilter::Username("USER1".to_string())),
+            ..Default::default()
+        }));
+        assert!(!options(RandomOptions {
+            author: Some(AuthorFilter::User(UserId(2), "user1".to_string())),
+            ..Default::default()
+        }));
+        assert!(options(RandomOptions {
+            max_age: Some(Duration::days(30)),
+            ..Default::default()
+        }));
+        assert!(!options(RandomOptions {
+            max_age: Some(Duration::days(7)),
+            ..Default::default()
+        }));
+        assert!(!options(RandomOptions {
+            exclude: HashSet::from([key.clone()]),
+            ..Default::default()
+        }));
+        assert!(!options(RandomOptions {
+            tag: Some("horror".to_string()),
+            ..Default::default()
+        }));
+
+        let no_repeat = RandomOptions {
+            no_repeat: Some(Duration::days(7)),
+            ..Default::default()
+        };
+        item.set_shown(now() - Duration::days(3));
+        assert!(!no_repeat.matches(&key, &item, now()));
+        item.set_shown(now() - Duration::days(8));
+        assert!(no_repeat.matches(&key, &item, now()));
+
+        item.set_read(now());
+        assert!(!RandomOptions::default().matches(&key, &item, now()));
+    }
+
+    #[test]
+    /// Test that older items get bigger weights, legacy ones count as the oldest
+    fn test_age_weights() {
+        let candidates = [
+            item(1, 0),
+            item(1, 9),
+            (Key::generate(), ContentItem::legacy("user1", "legacy")),
+        ];
+
+        assert_eq!(age_weights(&candidates, now()), [1.0, 10.0, 10.0]);
+    }
+
+    #[test]
+    /// Test that older items are picked more often
+    fn test_pick_older() {
+        let candidates = [item(1, 0), item(1, 99)];
+
+        let counts = count_picks(&candidates, Weighting::Older, None, now());
+
+        assert!(counts[&candidates[1].0] > counts.get(&candidates[0].0).copied().unwrap_or(0) * 10);
+    }
+
+    #[test]
+    /// Test that items of the last shown author are skipped while there are others
+    fn test_pick_alternate() {
+        let candidates = [item(1, 0), item(1, 1), item(2, 2)];
+        let last_shown = item(1, 3).1;
+
+        let counts = count_picks(&candidates, Weighting::Alternate, Some(&last_shown), now());
+        assert_eq!(counts.keys().collect::<Vec<_>>(), [&candidates[2].0]);
+
+        // nobody else to alternate with
+        let counts = count_picks(
+            &candidates[..2],
+            Weighting::Alternate,
+            Some(&last_shown),
+            now(),
+        );
+        assert_eq!(counts.len(), 2);
+    }
+
+    #[test]
+    /// Test that nothing is picked from an empty list
+    fn test_pick_empty() {
+        for weighting in [Weighting::Uniform, Weighting::Older, Weighting::Alternate] {
+            assert_eq!(pick(Vec::new(), weighting, None, now()), None);
+        }
+    }
+}
@@ -5,23 +5,257 @@
 //! but I don't want to pay for it currently.
 //!
 //! TODO: think about [Neon](https://neon.tech) as a solution for Postgres.
+//!
+//! Items are stored under `<list id>:item:<item key>` keys. Every list also has
+//! secondary indexes, which are kept in sync by Lua scripts, so that queries
+//! don't have to scan the whole database:
+//! - `<list id>:unread` – set of queued item keys: unread and not archived
+//! - `<list id>:archived` – set of archived item keys
+//! - `<list id>:author:<author>` – set of keys of items added by the author,
+//!   where author is `id:<user id>` or, for legacy items without author id, username
+//! - `<list id>:authors` – hash from item key to its author, so we know which
+//!   author index to clean up when an item is overwritten or deleted
+//!
+//! Settings of the list's chat are stored as JSON under `<list id>:settings`.
+//!
+//! Items are serialized in a versioned format (see `serialization`), outdated
+//! records are read as usual and rewritten in the current format on startup.
+//!
+//! Releases before lists were introduced stored items under bare Telegram
+//! message id keys, see `migrate_legacy_keys` to move them to a list.
+//!
+//! Tests require a live Redis, so they're ignored by default. To run them:
+//! `TEST_REDIS_URL=redis://... cargo test -- --ignored`
 
-use std::{fmt, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    time::Duration,
+};
 
-use bincode::{deserialize, serialize};
 use color_eyre::{
-    eyre::{eyre, WrapErr},
+    eyre::{bail, eyre, WrapErr},
     Result,
 };
-use redis::{aio::Connection, AsyncCommands, Client};
+use redis::{
+    aio::{ConnectionLike, ConnectionManager},
+    AsyncCommands, Client, Cmd, Pipeline, RedisError, RedisFuture, Script, Value,
+};
+use teloxide::types::UserId;
 use tokio::time::timeout;
 use tracing::{debug, info};
 
-use super::{ContentItem, Key, StorageBackend};
+use super::{random, ContentItem, Key, ListId, RandomOptions, StorageBackend};
+use crate::settings::ChatSettings;
+
+mod serialization;
+use serialization::{deserialize, is_outdated, serialize};
+
+/// Build Redis key for the item in the list
+fn item_key(list: &ListId, key: &Key) -> String {
+    format!("{list}:item:{}", key.as_ref())
+}
+
+/// Build Redis key for the set of queued items in the list
+fn unread_key(list: &ListId) -> String {
+    format!("{list}:unread")
+}
+
+/// Build Redis key for the set of archived items in the list
+fn archived_key(list: &ListId) -> String {
+    format!("{list}:archived")
+}
+
+/// Suffix of the chat settings key, see `settings_key`
+const SETTINGS_KEY_SUFFIX: &str = ":settings";
+
+/// Build Redis key for the settings of the list's chat
+fn settings_key(list: &ListId) -> String {
+    format!("{list}{SETTINGS_KEY_SUFFIX}")
+}
+
+/// Suffix of the authors hash key, see `authors_key`
+const AUTHORS_KEY_SUFFIX: &str = ":authors";
+
+/// Build Redis key for the hash mapping item keys to their authors
+fn authors_key(list: &ListId) -> String {
+    format!("{list}{AUTHORS_KEY_SUFFIX}")
+}
+
+/// Build prefix of Redis keys for the sets of items added by the author
+fn author_key_prefix(list: &ListId) -> String {
+    format!("{list}:author:")
+}
+
+/// Build author part of the author index key.
+///
+/// Usernames can't contain colons, so they never clash with ids.
+fn author_index(author_id: Option<UserId>, username: &str) -> String {
+    match author_id {
+        Some(author_id) => format!("id:{author_id}"),
+        None => username.to_string(),
+    }
+}
+
+/// Store the item and update indexes.
+///
+/// Scripts may only touch keys passed in `KEYS`, so the old author is read
+/// beforehand and the key of its index is passed, if the item has an author.
+/// If the author has been changed since, script returns -1 and should be retried.
+///
+/// If the expected old record is passed, the item is only stored when it
+/// hasn't been changed since, otherwise script returns 0.
+const SET_SCRIPT: &str = r"
+    -- KEYS: item, unread index, authors hash, new author index, archived index,
+    --       [old author index]
+    -- ARGV: item key, serialized item, author, is queued ('1' or '0'),
+    --       is archived ('1' or '0'), old author, [expected old record]
+    if ARGV[7] and redis.call('GET', KEYS[1]) ~= ARGV[7] then
+        return 0
+    end
+
+    local old_author = redis.call('HGET', KEYS[3], ARGV[1])
+    if old_author ~= (KEYS[6] ~= nil and ARGV[6]) then
+        return -1
+    end
+    if old_author and old_author ~= ARGV[3] then
+        redis.call('SREM', KEYS[6], ARGV[1])
+    end
+
+    redis.call('SET', KEYS[1], ARGV[2])
+    redis.call('HSET', KEYS[3], ARGV[1], ARGV[3])
+    redis.call('SADD', KEYS[4], ARGV[1])
+
+    if ARGV[4] == '1' then
+        redis.call('SADD', KEYS[2], ARGV[1])
+    else
+        redis.call('SREM', KEYS[2], ARGV[1])
+    end
+
+    if ARGV[5] == '1' then
+        redis.call('SADD', KEYS[5], ARGV[1])
+    else
+        redis.call('SREM', KEYS[5], ARGV[1])
+    end
+
+    return 1
+";
+
+/// How many times `update` retries when the item is modified concurrently.
+///
+/// Every round at least one of the competing writers wins,
+/// so this is also the number of concurrent writers we tolerate.
+const UPDATE_ATTEMPTS: usize = 32;
+
+/// Upper bound of random delay before `update` retries, spreads competing writers apart
+const UPDATE_BACKOFF_MAX: Duration = Duration::from_millis(10);
+
+/// Wait a random delay before retrying a concurrently modified item
+async fn backoff() {
+    tokio::time::sleep(UPDATE_BACKOFF_MAX.mul_f64(rand::random())).await;
+}
+
+/// Delete the item and remove it from indexes.
+///
+/// Like in `SET_SCRIPT`, the author index key is passed by the caller,
+/// script returns 0 if the author has been changed since it was read.
+const DELETE_SCRIPT: &str = r"
+    -- KEYS: item, unread index, authors hash, archived index, [author index]
+    -- ARGV: item key, author
+    local author = redis.call('HGET', KEYS[3], ARGV[1])
+    if author ~= (KEYS[5] ~= nil and ARGV[2]) then
+        return 0
+    end
+    if author then
+        redis.call('SREM', KEYS[5], ARGV[1])
+    end
+
+    redis.call('HDEL', KEYS[3], ARGV[1])
+    redis.call('SREM', KEYS[2], ARGV[1])
+    redis.call('SREM', KEYS[4], ARGV[1])
+    redis.call('DEL', KEYS[1])
+
+    return 1
+";
+
+/// Replace the record only if it hasn't been changed since we've read it
+const REWRITE_SCRIPT: &str = r"
+    -- KEYS: item
+    -- ARGV: old record, new record
+    if redis.call('GET', KEYS[1]) == ARGV[1] then
+        redis.call('SET', KEYS[1], ARGV[2])
+        return 1
+    end
+
+    return 0
+";
+
+/// Timeouts used while talking to Redis
+#[derive(Debug, Clone, Copy)]
+pub struct RedisTimeouts {
+    /// How long to wait for the initial connection to be established
+    pub connect: Duration,
+    /// How long to wait for a response to a single command (or pipeline),
+    /// including reconnection if the connection was dropped
+    pub response: Duration,
+}
+
+impl Default for RedisTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_millis(1500),
+            response: Duration::from_millis(1500),
+        }
+    }
+}
+
+/// Shared multiplexed connection, reconnecting on failures
+/// and failing commands that took longer than the response timeout
+#[derive(Clone)]
+struct Connection {
+    manager: ConnectionManager,
+    response_timeout: Duration,
+}
+
+impl Connection {
+    fn timed_out() -> RedisError {
+        io::Error::new(io::ErrorKind::TimedOut, "Redis response timed out").into()
+    }
+}
+
+impl ConnectionLike for Connection {
+    fn req_packed_command<'a>(&'a mut self, cmd: &'a Cmd) -> RedisFuture<'a, Value> {
+        Box::pin(async move {
+            timeout(self.response_timeout, self.manager.req_packed_command(cmd))
+                .await
+                .map_err(|_| Self::timed_out())?
+        })
+    }
+
+    fn req_packed_commands<'a>(
+        &'a mut self,
+        cmd: &'a Pipeline,
+        offset: usize,
+        count: usize,
+    ) -> RedisFuture<'a, Vec<Value>> {
+        Box::pin(async move {
+            timeout(
+                self.response_timeout,
+                self.manager.req_packed_commands(cmd, offset, count),
+            )
+            .await
+            .map_err(|_| Self::timed_out())?
+        })
+    }
+
+    fn get_db(&self) -> i64 {
+        self.manager.get_db()
+    }
+}
 
 #[derive(Clone)]
 pub struct RedisStorage {
-    client: Client,
+    connection: Connection,
 }
 
 impl fmt::Debug for RedisStorage {
@@ -31,10 +265,135 @@ impl fmt::Debug for RedisStorage {
 }
 
 impl RedisStorage {
-    pub async fn new(url: &str) -> Result<Self> {
+    #[tracing::instrument(err, skip(url))]
+    pub async fn new(url: &str, timeouts: RedisTimeouts) -> Result<Self> {
         let client = Client::open(url)?;
 
-        Ok(Self { client })
+        info!("Connecting to redis");
+
+        let manager = timeout(timeouts.connect, ConnectionManager::new(client))
+            .await
+            .wrap_err("connection didn't established before timeout")?
+            .wrap_err("Redis connection error")?;
+
+        info!("Connected to redis");
+
+        let storage = Self {
+            connection: Connection {
+                manager,
+                response_timeout: timeouts.response,
+            },
+        };
+        storage
+            .migrate_records()
+            .await
+            .wrap_err("failed to migrate items to the current schema version")?;
+
+        Ok(storage)
+    }
+
+    /// Rewrite all the records stored in outdated format using the current one.
+    ///
+    /// Records changed concurrently are skipped – they've been written
+    /// in the current format anyway.
+    #[tracing::instrument(err, skip(self))]
+    pub async fn migrate_records(&self) -> Result<()> {
+        let mut connection = self.connection();
+
+        let keys: Vec<String> = {
+            let mut iter = connection
+                .scan_match::<_, String>("*:item:*")
+                .await
+                .wrap_err("failed to scan item keys")?;
+
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+
+        let mut migrated = 0;
+        for key in keys {
+            let Some(old) = connection
+                .get::<_, Option<Vec<u8>>>(&key)
+                .await
+                .wrap_err_with(|| format!("failed to get item from Redis by key `{key}`"))?
+            else {
+                continue;
+            };
+            if !is_outdated(&old) {
+                continue;
+            }
+
+            let item = deserialize(&old)
+                .wrap_err_with(|| format!("failed to deserialize outdated item `{key}`"))?;
+            let new = serialize(&item)?;
+
+            let rewritten: bool = Script::new(REWRITE_SCRIPT)
+                .key(&key)
+                .arg(old)
+                .arg(new)
+                .invoke_async(&mut connection)
+                .await
+                .wrap_err_with(|| format!("failed to rewrite item `{key}`"))?;
+            if rewritten {
+                migrated += 1;
+            }
+        }
+
+        info!("Migrated {migrated} items to the current schema version");
+
+        Ok(())
+    }
+
+    /// Move items stored under bare message id keys to the given list.
+    ///
+    /// Keys are preserved, so buttons in the chat history keep working.
+    /// Safe to re-run: item is removed from the old key only after it's stored in the list.
+    #[tracing::instrument(err, skip(self))]
+    pub async fn migrate_legacy_keys(&mut self, list: &ListId) -> Result<()> {
+        let mut connection = self.connection();
+
+        let keys: Vec<String> = {
+            let mut iter = connection
+                .scan::<String>()
+                .await
+                .wrap_err("failed to scan keys")?;
+
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                if key.parse::<i32>().is_ok() {
+                    keys.push(key);
+                }
+            }
+            keys
+        };
+
+        let mut migrated = 0;
+        for key in keys {
+            let Some(item) = connection
+                .get::<_, Option<Vec<u8>>>(&key)
+                .await
+                .wrap_err_with(|| format!("failed to get legacy item by key `{key}`"))?
+            else {
+                continue;
+            };
+            let item = deserialize(&item)
+                .wrap_err_with(|| format!("failed to deserialize legacy item `{key}`"))?;
+
+            self.set(list, &Key(key.clone()), item).await?;
+            connection
+                .del::<_, ()>(&key)
+                .await
+                .wrap_err_with(|| format!("failed to delete legacy item `{key}`"))?;
+
+            migrated += 1;
+        }
+
+        info!("Moved {migrated} legacy items to list {list}");
+
+        Ok(())
     }
 
     pub fn into_storage(self) -> super::Storage<Self> {
@@ -43,35 +402,120 @@ impl RedisStorage {
 
     /// Method do get live redis connection.
     ///
-    /// Currently creates new connection every time.
-    /// Huge performance impact, but for current state of having only 2 (two) users
-    /// that's probably fine :)
-    #[tracing::instrument(skip(self))]
-    async fn connection(&self) -> Result<Connection> {
-        info!("Getting redis connection");
+    /// All the clones share one multiplexed connection, which is re-established
+    /// in background after being dropped, so this is cheap.
+    fn connection(&self) -> Connection {
+        self.connection.clone()
+    }
+
+    /// Store the item and update indexes, see `SET_SCRIPT`.
+    ///
+    /// Returns `false` if `expected` record was passed and the item has been changed since.
+    async fn store(
+        &self,
+        list: &ListId,
+        key: &Key,
+        value: &ContentItem,
+        expected: Option<&[u8]>,
+    ) -> Result<bool> {
+        let mut connection = self.connection();
+
+        let item = serialize(value).wrap_err("failed to serialize item in `set`")?;
+        let author_prefix = author_key_prefix(list);
+        let author = author_index(value.author_id(), value.author());
+        let script = Script::new(SET_SCRIPT);
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            let old_author = self.indexed_author(list, key).await?;
+
+            let mut invocation = script.key(item_key(list, key));
+            invocation
+                .key(unread_key(list))
+                .key(authors_key(list))
+                .key(format!("{author_prefix}{author}"))
+                .key(archived_key(list));
+            if let Some(old_author) = &old_author {
+                invocation.key(format!("{author_prefix}{old_author}"));
+            }
+            invocation
+                .arg(key.as_ref())
+                .arg(&item)
+                .arg(&author)
+                .arg(if value.is_queued() { "1" } else { "0" })
+                .arg(if value.is_archived() { "1" } else { "0" })
+                .arg(old_author.unwrap_or_default());
+            if let Some(expected) = expected {
+                invocation.arg(expected);
+            }
+
+            let stored: i8 = invocation
+                .invoke_async(&mut connection)
+                .await
+                .wrap_err("failed to set item via Redis?")?;
+            if stored >= 0 {
+                return Ok(stored == 1);
+            }
+
+            debug!("author of item {key:?} was changed concurrently, retrying");
+            backoff().await;
+        }
+
+        bail!("failed to set item {key:?}: it's being modified concurrently")
+    }
+
+    /// Author the item is indexed under, its index key has to be passed to scripts
+    async fn indexed_author(&self, list: &ListId, key: &Key) -> Result<Option<String>> {
+        self.connection()
+            .hget(authors_key(list), key.as_ref())
+            .await
+            .wrap_err_with(|| format!("failed to get author of item {key:?}"))
+    }
 
-        let connection = timeout(
-            Duration::from_millis(1500),
-            self.client.get_tokio_connection(),
-        )
-        .await
-        .wrap_err("connection didn't established before timeout")?
-        .wrap_err("Redis connection error")?;
+    /// Fetch items of the list by their keys with a single `MGET`.
+    ///
+    /// Keys which have been deleted in the meantime are skipped.
+    async fn get_many(
+        &self,
+        list: &ListId,
+        keys: Vec<String>,
+    ) -> Result<HashMap<Key, ContentItem>> {
+        if keys.is_empty() {
+            return Ok(HashMap::new());
+        }
 
-        info!("Got redis connection");
+        let mut connection = self.connection();
 
-        Ok(connection)
+        let redis_keys: Vec<String> = keys
+            .iter()
+            .map(|key| item_key(list, &Key(key.clone())))
+            .collect();
+        let items: Vec<Option<Vec<u8>>> = redis::cmd("MGET")
+            .arg(redis_keys)
+            .query_async(&mut connection)
+            .await
+            .wrap_err("failed to get items from Redis")?;
+
+        keys.into_iter()
+            .zip(items)
+            .filter_map(|(key, item)| Some((key, item?)))
+            .map(|(key, item)| {
+                let item =
+                    deserialize(&item).wrap_err("failed to deserialize item in `get_many`")?;
+
+                Ok((Key(key), item))
+            })
+            .collect()
     }
 }
 
 #[async_trait::async_trait]
 impl StorageBackend for RedisStorage {
     #[tracing::instrument(err, skip(self))]
-    async fn get(&self, key: &Key) -> Result<Option<ContentItem>> {
-        let mut connection = self.connection().await?;
+    async fn get(&self, list: &ListId, key: &Key) -> Result<Option<ContentItem>> {
+        let mut connection = self.connection();
 
         let item: Option<Vec<u8>> = connection
-            .get(key.as_ref())
+            .get(item_key(list, key))
             .await
             .wrap_err_with(|| format!("failed to get item from Redis by key `{key:?}`"))?;
 
@@ -81,61 +525,131 @@ impl StorageBackend for RedisStorage {
     }
 
     #[tracing::instrument(err, skip(self, value))]
-    async fn set(&mut self, key: &Key, value: ContentItem) -> Result<()> {
-        let mut connection = self.connection().await?;
+    async fn set(&mut self, list: &ListId, key: &Key, value: ContentItem) -> Result<()> {
+        self.store(list, key, &value, None).await?;
 
-        let item = serialize(&value).wrap_err("failed to serialize item in `set`")?;
-        connection
-            .set(key.as_ref(), item)
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self, f))]
+    async fn update<F>(&mut self, list: &ListId, key: &Key, mut f: F) -> Result<Option<ContentItem>>
+    where
+        F: FnMut(&mut ContentItem) + Send,
+    {
+        let mut connection = self.connection();
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            let Some(old) = connection
+                .get::<_, Option<Vec<u8>>>(item_key(list, key))
+                .await
+                .wrap_err_with(|| format!("failed to get item from Redis by key `{key:?}`"))?
+            else {
+                return Ok(None);
+            };
+
+            let mut item = deserialize(&old).wrap_err("failed to deserialize item in `update`")?;
+            f(&mut item);
+
+            if self.store(list, key, &item, Some(&old)).await? {
+                return Ok(Some(item));
+            }
+
+            debug!("item {key:?} was modified concurrently, retrying");
+            backoff().await;
+        }
+
+        bail!("failed to update item {key:?}: it's being modified concurrently")
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_all(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        let mut connection = self.connection();
+
+        let keys: Vec<String> = connection
+            .smembers(unread_key(list))
             .await
-            .wrap_err("failed to set item via Redis?")?;
+            .wrap_err("failed to get unread keys from Redis")?;
 
-        Ok(())
+        self.get_many(list, keys).await
     }
 
     #[tracing::instrument(err, skip(self))]
-    async fn get_all(&self) -> Result<std::collections::HashMap<Key, ContentItem>> {
-        let mut connection = self.connection().await?;
+    async fn get_archived(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        let mut connection = self.connection();
 
         let keys: Vec<String> = connection
-            .keys("*")
+            .smembers(archived_key(list))
             .await
-            .wrap_err("failed to get keys from Redis")?;
+            .wrap_err("failed to get archived keys from Redis")?;
 
-        let mut items = std::collections::HashMap::new();
-        for key in keys {
-            let item = connection
-                .get::<_, Option<Vec<u8>>>(&key).await
-                .wrap_err_with(|| format!("failed to get item from Redis by key `{key}`"))?
-                .ok_or_else(|| eyre!("failed to get item from Redis by key that Redis provided. Most likely we've encountered a race here"))?;
+        self.get_many(list, keys).await
+    }
 
-            let item: ContentItem =
-                deserialize(&item).wrap_err("failed to deserialize item in `get_all`")?;
+    #[tracing::instrument(err, skip(self))]
+    async fn dump(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        let mut connection = self.connection();
 
-            if item.is_read() {
-                continue;
-            }
+        // every item, read or unread, has an entry in the authors hash
+        let keys: Vec<String> = connection
+            .hkeys(authors_key(list))
+            .await
+            .wrap_err("failed to get item keys from Redis")?;
+
+        self.get_many(list, keys).await
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_lists(&self) -> Result<Vec<ListId>> {
+        let mut connection = self.connection();
+
+        // lists with items have authors hashes, lists of chats with settings – settings keys
+        let mut lists = HashSet::new();
+        for suffix in [AUTHORS_KEY_SUFFIX, SETTINGS_KEY_SUFFIX] {
+            let mut iter = connection
+                .scan_match::<_, String>(format!("*{suffix}"))
+                .await
+                .wrap_err_with(|| format!("failed to scan `*{suffix}` keys"))?;
 
-            items.insert(Key(key), item);
+            while let Some(key) = iter.next_item().await {
+                if let Some(list) = key.strip_suffix(suffix).and_then(|list| list.parse().ok()) {
+                    lists.insert(ListId(list));
+                }
+            }
         }
-        Ok(items)
+
+        Ok(lists.into_iter().collect())
     }
 
     #[tracing::instrument(err, skip(self))]
     async fn get_user_items(
         &self,
-        user: &str,
-    ) -> Result<std::collections::HashMap<Key, ContentItem>> {
-        // TODO: think about prefixing keys with user name
-        let mut map = self.get_all().await?;
-        map.retain(|_, item| item.author() == user);
+        list: &ListId,
+        user_id: UserId,
+        username: &str,
+    ) -> Result<HashMap<Key, ContentItem>> {
+        let mut connection = self.connection();
+
+        let author_prefix = author_key_prefix(list);
+        let (mut keys, legacy_keys): (Vec<String>, Vec<String>) = redis::pipe()
+            .sinter(&[
+                unread_key(list),
+                format!("{author_prefix}{}", author_index(Some(user_id), username)),
+            ])
+            .sinter(&[
+                unread_key(list),
+                format!("{author_prefix}{}", author_index(None, username)),
+            ])
+            .query_async(&mut connection)
+            .await
+            .wrap_err("failed to get user's unread keys from Redis")?;
+        keys.extend(legacy_keys);
 
-        Ok(map)
+        self.get_many(list, keys).await
     }
 
     #[tracing::instrument(err, ret, skip(self))]
     async fn get_now(&self) -> Result<time::OffsetDateTime> {
-        let mut connection = self.connection().await?;
+        let mut connection = self.connection();
 
         let (seconds, _usecs): (i64, i64) = redis::cmd("TIME")
             .query_async(&mut connection)
@@ -146,53 +660,109 @@ impl StorageBackend for RedisStorage {
     }
 
     #[tracing::instrument(err, skip(self))]
-    async fn delete(&mut self, key: &Key) -> Result<()> {
-        let mut connection = self.connection().await?;
+    async fn delete(&mut self, list: &ListId, key: &Key) -> Result<()> {
+        let mut connection = self.connection();
+        let script = Script::new(DELETE_SCRIPT);
+
+        for _ in 0..UPDATE_ATTEMPTS {
+            let author = self.indexed_author(list, key).await?;
+
+            let mut invocation = script.key(item_key(list, key));
+            invocation
+                .key(unread_key(list))
+                .key(authors_key(list))
+                .key(archived_key(list));
+            if let Some(author) = &author {
+                invocation.key(format!("{}{author}", author_key_prefix(list)));
+            }
 
-        connection
-            .del(key.as_ref())
-            .await
-            .wrap_err_with(|| format!("failed to delete item by key {key:?}"))?;
-        Ok(())
+            let deleted: bool = invocation
+                .arg(key.as_ref())
+                .arg(author.unwrap_or_default())
+                .invoke_async(&mut connection)
+                .await
+                .wrap_err_with(|| format!("failed to delete item by key {key:?}"))?;
+            if deleted {
+                return Ok(());
+            }
+
+            debug!("author of item {key:?} was changed concurrently, retrying");
+            backoff().await;
+        }
+
+        bail!("failed to delete item {key:?}: it's being modified concurrently")
     }
 
     #[tracing::instrument(fields(random_key), err, skip(self))]
-    async fn get_random(&self) -> Result<Option<(Key, ContentItem)>> {
-        let mut connection = self.connection().await?;
+    async fn get_random(
+        &self,
+        list: &ListId,
+        options: &RandomOptions,
+    ) -> Result<Option<(Key, ContentItem)>> {
+        if !options.is_uniform() {
+            return random::get_random(self, list, options).await;
+        }
 
-        // TODO: this will be slow as hell when we'll have a lot of read items
-        loop {
-            let key: Option<String> = redis::cmd("RANDOMKEY")
-                .query_async(&mut connection)
-                .await
-                .wrap_err("failed to get random key from Redis")?;
+        let mut connection = self.connection();
 
-            debug!("got following random key: {key:?}");
+        let key: Option<String> = connection
+            .srandmember(unread_key(list))
+            .await
+            .wrap_err("failed to get random unread key from Redis")?;
+
+        let Some(key) = key else {
+            return Ok(None);
+        };
+        tracing::Span::current().record("random_key", &key);
+
+        let key = Key(key);
+        let item = self
+            .get(list, &key)
+            .await?
+            .ok_or_else(|| eyre!("key {key:?} got from SRANDMEMBER doesn't exist! Most likely we've encountered a race here"))?;
+
+        // snoozed items stay in the unread set, so pick among the rest when we've hit one
+        if item.is_snoozed(self.get_now().await?) {
+            return random::get_random(self, list, options).await;
+        }
 
-            if let Some(key) = key {
-                tracing::Span::current().record("random_key", &key);
+        Ok(Some((key, item)))
+    }
 
-                let item: Vec<u8> = connection
-                    .get::<_, Option<Vec<u8>>>(&key)
-                    .await?
-                    .ok_or_else(|| eyre!("key {key:?} got from RANDOMKEY doesn't exist! Most likely we've encountered a race here"))?;
-                let item: ContentItem =
-                    deserialize(&item).wrap_err("failed to deserialize item in `get_random`")?;
+    #[tracing::instrument(err, skip(self))]
+    async fn get_settings(&self, list: &ListId) -> Result<ChatSettings> {
+        let mut connection = self.connection();
 
-                if item.is_read() {
-                    continue;
-                }
+        let settings: Option<String> = connection
+            .get(settings_key(list))
+            .await
+            .wrap_err("failed to get chat settings from Redis")?;
+
+        settings
+            .map(|settings| {
+                serde_json::from_str(&settings).wrap_err("failed to deserialize chat settings")
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
 
-                return Ok(Some((key.into(), item)));
-            } else {
-                return Ok(None);
-            }
-        }
+    #[tracing::instrument(err, skip(self))]
+    async fn set_settings(&mut self, list: &ListId, settings: &ChatSettings) -> Result<()> {
+        let mut connection = self.connection();
+        let settings =
+            serde_json::to_string(settings).wrap_err("failed to serialize chat settings")?;
+
+        connection
+            .set::<_, _, ()>(settings_key(list), settings)
+            .await
+            .wrap_err("failed to set chat settings via Redis")?;
+
+        Ok(())
     }
 
     #[tracing::instrument(err, skip(self))]
     async fn health_check(&self) -> Result<()> {
-        let mut connection = self.connection().await?;
+        let mut connection = self.connection();
 
         let _: String = redis::cmd("PING")
             .query_async(&mut connection)
@@ -202,3 +772,20 @@ impl StorageBackend for RedisStorage {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::conformance::conformance_tests;
+
+    async fn storage() -> RedisStorage {
+        let url = std::env::var("TEST_REDIS_URL").expect("TEST_REDIS_URL unspecified");
+
+        RedisStorage::new(&url, RedisTimeouts::default())
+            .await
+            .unwrap()
+    }
+
+    conformance_tests!(storage().await, #[ignore = "requires TEST_REDIS_URL"]);
+}
@@ -0,0 +1,617 @@
+//! Versioned serialization format for items stored in Redis.
+//!
+//! Every record is `MAGIC`, followed by schema version (u16, little endian)
+//! and a bincode payload. Records written before versioning was introduced
+//! are raw bincode blobs – they're treated as version 0.
+//!
+//! When `ContentItem` changes, bump `CURRENT_VERSION`, freeze the previous layout
+//! in a private struct and add an upgrade arm to `deserialize`.
+
+use bincode::Options;
+use color_eyre::{
+    eyre::{bail, WrapErr},
+    Result,
+};
+use serde::Deserialize;
+use teloxide::types::UserId;
+use time::OffsetDateTime;
+use url::Url;
+
+use super::ContentItem;
+use crate::content_item::{Attachment, Category};
+
+/// Marks versioned records.
+///
+/// Legacy records start with u64 length of the author name, and
+/// these bytes would mean a name of at least ~5.2M characters, so there's no ambiguity.
+const MAGIC: &[u8; 3] = b"CWO";
+
+/// Version of the records written by the current code
+pub(super) const CURRENT_VERSION: u16 = 8;
+
+/// Length of the header of versioned records
+const HEADER_LEN: usize = MAGIC.len() + std::mem::size_of::<u16>();
+
+/// Serialize item with the current schema version
+pub(super) fn serialize(item: &ContentItem) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(HEADER_LEN);
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&CURRENT_VERSION.to_le_bytes());
+
+    bincode::serialize_into(&mut bytes, item).wrap_err("failed to serialize item")?;
+
+    Ok(bytes)
+}
+
+/// Get schema version of the record
+pub(super) fn version(bytes: &[u8]) -> u16 {
+    match bytes.strip_prefix(MAGIC.as_slice()) {
+        Some([lo, hi, ..]) => u16::from_le_bytes([*lo, *hi]),
+        _ => 0,
+    }
+}
+
+/// Check whether the record should be rewritten in the current format
+pub(super) fn is_outdated(bytes: &[u8]) -> bool {
+    version(bytes) < CURRENT_VERSION
+}
+
+/// Deserialize record of any known version, upgrading it to the current `ContentItem`
+pub(super) fn deserialize(bytes: &[u8]) -> Result<ContentItem> {
+    let version = version(bytes);
+    let payload = if version == 0 {
+        bytes
+    } else {
+        &bytes[HEADER_LEN..]
+    };
+
+    match version {
+        // legacy records have the same layout as the first versioned ones
+        0 | 1 => decode::<ContentItemV1>(payload).map(Into::into),
+        2 => decode::<ContentItemV2>(payload).map(Into::into),
+        3 => decode::<ContentItemV3>(payload).map(Into::into),
+        4 => decode::<ContentItemV4>(payload).map(Into::into),
+        5 => decode::<ContentItemV5>(payload).map(Into::into),
+        6 => decode::<ContentItemV6>(payload).map(Into::into),
+        7 => decode::<ContentItemV7>(payload).map(Into::into),
+        CURRENT_VERSION => decode(payload),
+        _ => bail!("unknown item schema version {version}, was it written by a newer release?"),
+    }
+}
+
+/// Layout of versions 0 and 1: no author id and creation time
+#[derive(Deserialize)]
+struct ContentItemV1 {
+    author: String,
+    content: String,
+    read_at: Option<OffsetDateTime>,
+}
+
+impl From<ContentItemV1> for ContentItem {
+    fn from(old: ContentItemV1) -> Self {
+        let mut item = ContentItem::legacy(old.author, old.content);
+        if let Some(read_at) = old.read_at {
+            item.set_read(read_at);
+        }
+
+        item
+    }
+}
+
+/// Layout of version 2: no archive
+#[derive(Deserialize)]
+struct ContentItemV2 {
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    added_at: Option<OffsetDateTime>,
+    read_at: Option<OffsetDateTime>,
+}
+
+impl From<ContentItemV2> for ContentItem {
+    fn from(old: ContentItemV2) -> Self {
+        let mut item = match (old.author_id, old.added_at) {
+            (Some(author_id), Some(added_at)) => {
+                ContentItem::new(author_id, old.author, old.content, added_at)
+            }
+            _ => ContentItem::legacy(old.author, old.content),
+        };
+        if let Some(read_at) = old.read_at {
+            item.set_read(read_at);
+        }
+
+        item
+    }
+}
+
+/// Layout of version 3: no time the item was shown by `/random`
+#[derive(Deserialize)]
+struct ContentItemV3 {
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    added_at: Option<OffsetDateTime>,
+    read_at: Option<OffsetDateTime>,
+    archived_at: Option<OffsetDateTime>,
+}
+
+impl From<ContentItemV3> for ContentItem {
+    fn from(old: ContentItemV3) -> Self {
+        let mut item = ContentItem::from(ContentItemV2 {
+            author_id: old.author_id,
+            author: old.author,
+            content: old.content,
+            added_at: old.added_at,
+            read_at: old.read_at,
+        });
+        if let Some(archived_at) = old.archived_at {
+            item.archive(archived_at);
+        }
+
+        item
+    }
+}
+
+/// Layout of version 4: no snoozes
+#[derive(Deserialize)]
+struct ContentItemV4 {
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    added_at: Option<OffsetDateTime>,
+    read_at: Option<OffsetDateTime>,
+    archived_at: Option<OffsetDateTime>,
+    shown_at: Option<OffsetDateTime>,
+}
+
+impl From<ContentItemV4> for ContentItem {
+    fn from(old: ContentItemV4) -> Self {
+        let mut item = ContentItem::from(ContentItemV3 {
+            author_id: old.author_id,
+            author: old.author,
+            content: old.content,
+            added_at: old.added_at,
+            read_at: old.read_at,
+            archived_at: old.archived_at,
+        });
+        if let Some(shown_at) = old.shown_at {
+            item.set_shown(shown_at);
+        }
+
+        item
+    }
+}
+
+/// Layout of version 5: no category and tags
+#[derive(Deserialize)]
+struct ContentItemV5 {
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    added_at: Option<OffsetDateTime>,
+    read_at: Option<OffsetDateTime>,
+    archived_at: Option<OffsetDateTime>,
+    shown_at: Option<OffsetDateTime>,
+    snoozed_until: Option<OffsetDateTime>,
+}
+
+impl From<ContentItemV5> for ContentItem {
+    fn from(old: ContentItemV5) -> Self {
+        let mut item = ContentItem::from(ContentItemV4 {
+            author_id: old.author_id,
+            author: old.author,
+            content: old.content,
+            added_at: old.added_at,
+            read_at: old.read_at,
+            archived_at: old.archived_at,
+            shown_at: old.shown_at,
+        });
+        if let Some(snoozed_until) = old.snoozed_until {
+            item.snooze(snoozed_until);
+        }
+
+        item
+    }
+}
+
+/// Layout of version 6: no links and attachment
+#[derive(Deserialize)]
+struct ContentItemV6 {
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    added_at: Option<OffsetDateTime>,
+    read_at: Option<OffsetDateTime>,
+    archived_at: Option<OffsetDateTime>,
+    shown_at: Option<OffsetDateTime>,
+    snoozed_until: Option<OffsetDateTime>,
+    category: Option<Category>,
+    tags: Vec<String>,
+}
+
+impl From<ContentItemV6> for ContentItem {
+    fn from(old: ContentItemV6) -> Self {
+        let mut item = ContentItem::from(ContentItemV5 {
+            author_id: old.author_id,
+            author: old.author,
+            content: old.content,
+            added_at: old.added_at,
+            read_at: old.read_at,
+            archived_at: old.archived_at,
+            shown_at: old.shown_at,
+            snoozed_until: old.snoozed_until,
+        });
+        item.set_category(old.category);
+        item.set_tags(old.tags);
+
+        item
+    }
+}
+
+/// Layout of version 7: no source and forward origin
+#[derive(Deserialize)]
+struct ContentItemV7 {
+    author_id: Option<UserId>,
+    author: String,
+    content: String,
+    added_at: Option<OffsetDateTime>,
+    read_at: Option<OffsetDateTime>,
+    archived_at: Option<OffsetDateTime>,
+    shown_at: Option<OffsetDateTime>,
+    snoozed_until: Option<OffsetDateTime>,
+    category: Option<Category>,
+    tags: Vec<String>,
+    links: Vec<Url>,
+    attachment: Option<Attachment>,
+}
+
+impl From<ContentItemV7> for ContentItem {
+    fn from(old: ContentItemV7) -> Self {
+        let mut item = ContentItem::from(ContentItemV6 {
+            author_id: old.author_id,
+            author: old.author,
+            content: old.content,
+            added_at: old.added_at,
+            read_at: old.read_at,
+            archived_at: old.archived_at,
+            shown_at: old.shown_at,
+            snoozed_until: old.snoozed_until,
+            category: old.category,
+            tags: old.tags,
+        });
+        item.set_links(old.links);
+        item.set_attachment(old.attachment);
+
+        item
+    }
+}
+
+/// Decode bincode payload, rejecting trailing bytes
+fn decode<'a, T: serde::Deserialize<'a>>(payload: &'a [u8]) -> Result<T> {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(payload)
+        .wrap_err("failed to deserialize item payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::content_item::AttachmentKind;
+
+    use super::*;
+
+    fn item() -> ContentItem {
+        ContentItem::new(
+            UserId(1),
+            "alice",
+            "https://example.com",
+            OffsetDateTime::UNIX_EPOCH,
+        )
+    }
+
+    /// Serialize item in the version 1 layout
+    fn item_v1(read_at: Option<OffsetDateTime>) -> Vec<u8> {
+        #[derive(serde::Serialize)]
+        struct ContentItemV1<'a> {
+            author: &'a str,
+            content: &'a str,
+            read_at: Option<OffsetDateTime>,
+        }
+
+        bincode::serialize(&ContentItemV1 {
+            author: "alice",
+            content: "https://example.com",
+            read_at,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    /// Test that items survive serialization roundtrip
+    fn test_roundtrip() {
+        let bytes = serialize(&item()).unwrap();
+
+        assert_eq!(version(&bytes), CURRENT_VERSION);
+        assert!(!is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), item());
+    }
+
+    #[test]
+    /// Test that raw bincode records are read and reported as outdated
+    fn test_legacy() {
+        let bytes = item_v1(None);
+
+        assert_eq!(version(&bytes), 0);
+        assert!(is_outdated(&bytes));
+        assert_eq!(
+            deserialize(&bytes).unwrap(),
+            ContentItem::legacy("alice", "https://example.com")
+        );
+    }
+
+    #[test]
+    /// Test that version 1 records are upgraded, keeping read status
+    fn test_upgrade_from_v1() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&item_v1(Some(OffsetDateTime::UNIX_EPOCH)));
+
+        let mut expected = ContentItem::legacy("alice", "https://example.com");
+        expected.set_read(OffsetDateTime::UNIX_EPOCH);
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that version 2 records are upgraded, keeping author id and read status
+    fn test_upgrade_from_v2() {
+        #[derive(serde::Serialize)]
+        struct ContentItemV2<'a> {
+            author_id: Option<UserId>,
+            author: &'a str,
+            content: &'a str,
+            added_at: Option<OffsetDateTime>,
+            read_at: Option<OffsetDateTime>,
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bincode::serialize_into(
+            &mut bytes,
+            &ContentItemV2 {
+                author_id: Some(UserId(1)),
+                author: "alice",
+                content: "https://example.com",
+                added_at: Some(OffsetDateTime::UNIX_EPOCH),
+                read_at: Some(OffsetDateTime::UNIX_EPOCH),
+            },
+        )
+        .unwrap();
+
+        let mut expected = item();
+        expected.set_read(OffsetDateTime::UNIX_EPOCH);
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that version 3 records are upgraded, keeping archive status
+    fn test_upgrade_from_v3() {
+        #[derive(serde::Serialize)]
+        struct ContentItemV3<'a> {
+            author_id: Option<UserId>,
+            author: &'a str,
+            content: &'a str,
+            added_at: Option<OffsetDateTime>,
+            read_at: Option<OffsetDateTime>,
+            archived_at: Option<OffsetDateTime>,
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&3u16.to_le_bytes());
+        bincode::serialize_into(
+            &mut bytes,
+            &ContentItemV3 {
+                author_id: Some(UserId(1)),
+                author: "alice",
+                content: "https://example.com",
+                added_at: Some(OffsetDateTime::UNIX_EPOCH),
+                read_at: None,
+                archived_at: Some(OffsetDateTime::UNIX_EPOCH),
+            },
+        )
+        .unwrap();
+
+        let mut expected = item();
+        expected.archive(OffsetDateTime::UNIX_EPOCH);
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that version 4 records are upgraded, keeping the time the item was shown
+    fn test_upgrade_from_v4() {
+        #[derive(serde::Serialize)]
+        struct ContentItemV4<'a> {
+            author_id: Option<UserId>,
+            author: &'a str,
+            content: &'a str,
+            added_at: Option<OffsetDateTime>,
+            read_at: Option<OffsetDateTime>,
+            archived_at: Option<OffsetDateTime>,
+            shown_at: Option<OffsetDateTime>,
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&4u16.to_le_bytes());
+        bincode::serialize_into(
+            &mut bytes,
+            &ContentItemV4 {
+                author_id: Some(UserId(1)),
+                author: "alice",
+                content: "https://example.com",
+                added_at: Some(OffsetDateTime::UNIX_EPOCH),
+                read_at: None,
+                archived_at: None,
+                shown_at: Some(OffsetDateTime::UNIX_EPOCH),
+            },
+        )
+        .unwrap();
+
+        let mut expected = item();
+        expected.set_shown(OffsetDateTime::UNIX_EPOCH);
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that version 5 records are upgraded, keeping the snooze
+    fn test_upgrade_from_v5() {
+        #[derive(serde::Serialize)]
+        struct ContentItemV5<'a> {
+            author_id: Option<UserId>,
+            author: &'a str,
+            content: &'a str,
+            added_at: Option<OffsetDateTime>,
+            read_at: Option<OffsetDateTime>,
+            archived_at: Option<OffsetDateTime>,
+            shown_at: Option<OffsetDateTime>,
+            snoozed_until: Option<OffsetDateTime>,
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&5u16.to_le_bytes());
+        bincode::serialize_into(
+            &mut bytes,
+            &ContentItemV5 {
+                author_id: Some(UserId(1)),
+                author: "alice",
+                content: "https://example.com",
+                added_at: Some(OffsetDateTime::UNIX_EPOCH),
+                read_at: None,
+                archived_at: None,
+                shown_at: None,
+                snoozed_until: Some(OffsetDateTime::UNIX_EPOCH),
+            },
+        )
+        .unwrap();
+
+        let mut expected = item();
+        expected.snooze(OffsetDateTime::UNIX_EPOCH);
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that version 6 records are upgraded, keeping category and tags
+    fn test_upgrade_from_v6() {
+        #[derive(serde::Serialize)]
+        struct ContentItemV6<'a> {
+            author_id: Option<UserId>,
+            author: &'a str,
+            content: &'a str,
+            added_at: Option<OffsetDateTime>,
+            read_at: Option<OffsetDateTime>,
+            archived_at: Option<OffsetDateTime>,
+            shown_at: Option<OffsetDateTime>,
+            snoozed_until: Option<OffsetDateTime>,
+            category: Option<Category>,
+            tags: &'a [&'a str],
+        }
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&6u16.to_le_bytes());
+        bincode::serialize_into(
+            &mut bytes,
+            &ContentItemV6 {
+                author_id: Some(UserId(1)),
+                author: "alice",
+                content: "https://example.com",
+                added_at: Some(OffsetDateTime::UNIX_EPOCH),
+                read_at: None,
+                archived_at: None,
+                shown_at: None,
+                snoozed_until: None,
+                category: Some(Category::Movie),
+                tags: &["horror", "short"],
+            },
+        )
+        .unwrap();
+
+        let mut expected = item();
+        expected.set_category(Some(Category::Movie));
+        expected.set_tags(["horror", "short"]);
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that version 7 records are upgraded, keeping links and the attachment
+    fn test_upgrade_from_v7() {
+        #[derive(serde::Serialize)]
+        struct ContentItemV7<'a> {
+            author_id: Option<UserId>,
+            author: &'a str,
+            content: &'a str,
+            added_at: Option<OffsetDateTime>,
+            read_at: Option<OffsetDateTime>,
+            archived_at: Option<OffsetDateTime>,
+            shown_at: Option<OffsetDateTime>,
+            snoozed_until: Option<OffsetDateTime>,
+            category: Option<Category>,
+            tags: &'a [&'a str],
+            links: &'a [Url],
+            attachment: Option<Attachment>,
+        }
+
+        let links = [Url::parse("https://example.com/trailer").unwrap()];
+        let attachment = Attachment {
+            kind: AttachmentKind::Photo,
+            file_id: "file".to_string(),
+        };
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&7u16.to_le_bytes());
+        bincode::serialize_into(
+            &mut bytes,
+            &ContentItemV7 {
+                author_id: Some(UserId(1)),
+                author: "alice",
+                content: "https://example.com",
+                added_at: Some(OffsetDateTime::UNIX_EPOCH),
+                read_at: None,
+                archived_at: None,
+                shown_at: None,
+                snoozed_until: None,
+                category: None,
+                tags: &[],
+                links: &links,
+                attachment: Some(attachment.clone()),
+            },
+        )
+        .unwrap();
+
+        let mut expected = item();
+        expected.set_links(links);
+        expected.set_attachment(Some(attachment));
+
+        assert!(is_outdated(&bytes));
+        assert_eq!(deserialize(&bytes).unwrap(), expected);
+    }
+
+    #[test]
+    /// Test that records from the future are rejected instead of being misread
+    fn test_unknown_version() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&(CURRENT_VERSION + 1).to_le_bytes());
+        bytes.extend_from_slice(&bincode::serialize(&item()).unwrap());
+
+        assert!(deserialize(&bytes).is_err());
+    }
+}
@@ -0,0 +1,510 @@
+//! SQLite storage backend.
+//!
+//! Durable single-file storage for self-hosted deployments,
+//! schema is kept in `migrations/sqlite` and applied on startup.
+
+use std::{collections::HashMap, path::Path};
+
+use color_eyre::{eyre::WrapErr, Result};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow},
+    Row, SqliteExecutor,
+};
+use teloxide::types::{ChatId, MessageId, UserId};
+use time::OffsetDateTime;
+use url::Url;
+
+use super::{random, ContentItem, HistoryFilter, Key, ListId, RandomOptions, StorageBackend};
+use crate::{
+    content_item::{Attachment, Category, ForwardOrigin, Source},
+    settings::ChatSettings,
+};
+
+#[derive(Debug, Clone)]
+pub struct SqliteStorage {
+    pool: SqlitePool,
+}
+
+impl SqliteStorage {
+    /// Open (or create) database file at `path` and apply pending migrations
+    pub async fn new(path: impl AsRef<Path>) -> Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .connect_with(options)
+            .await
+            .wrap_err("failed to open SQLite database")?;
+
+        Self::from_pool(pool).await
+    }
+
+    /// Create storage on top of existing pool, applying pending migrations
+    pub async fn from_pool(pool: SqlitePool) -> Result<Self> {
+        sqlx::migrate!("migrations/sqlite")
+            .run(&pool)
+            .await
+            .wrap_err("failed to run SQLite migrations")?;
+
+        Ok(Self { pool })
+    }
+
+    pub fn into_storage(self) -> super::Storage<Self> {
+        super::Storage { backend: self }
+    }
+}
+
+/// Columns of the `items` table read by `from_row`
+const ITEM_COLUMNS: &str =
+    "key, author_id, author, content, added_at, read_at, archived_at, shown_at, snoozed_until, \
+    category, tags, links, attachment_kind, attachment_file_id, source_chat_id, source_message_id, \
+    forward_author, forward_chat_id, forward_message_id";
+
+/// Convert `items` table row to (key, item) pair
+fn from_row(row: SqliteRow) -> Result<(Key, ContentItem)> {
+    let key: String = row.try_get("key")?;
+    let author_id: Option<i64> = row.try_get("author_id")?;
+    let author: String = row.try_get("author")?;
+    let content: String = row.try_get("content")?;
+    let added_at: Option<OffsetDateTime> = row.try_get("added_at")?;
+    let read_at: Option<OffsetDateTime> = row.try_get("read_at")?;
+    let archived_at: Option<OffsetDateTime> = row.try_get("archived_at")?;
+    let shown_at: Option<OffsetDateTime> = row.try_get("shown_at")?;
+    let snoozed_until: Option<OffsetDateTime> = row.try_get("snoozed_until")?;
+    let category: Option<String> = row.try_get("category")?;
+    let tags: String = row.try_get("tags")?;
+    let links: String = row.try_get("links")?;
+    let attachment_kind: Option<String> = row.try_get("attachment_kind")?;
+    let attachment_file_id: Option<String> = row.try_get("attachment_file_id")?;
+    let source_chat_id: Option<i64> = row.try_get("source_chat_id")?;
+    let source_message_id: Option<i32> = row.try_get("source_message_id")?;
+    let forward_author: Option<String> = row.try_get("forward_author")?;
+    let forward_chat_id: Option<i64> = row.try_get("forward_chat_id")?;
+    let forward_message_id: Option<i32> = row.try_get("forward_message_id")?;
+
+    let mut item = match (author_id, added_at) {
+        (Some(author_id), Some(added_at)) => {
+            ContentItem::new(UserId(author_id as u64), author, content, added_at)
+        }
+        _ => ContentItem::legacy(author, content),
+    };
+    if let Some(read_at) = read_at {
+        item.set_read(read_at);
+    }
+    if let Some(archived_at) = archived_at {
+        item.archive(archived_at);
+    }
+    if let Some(shown_at) = shown_at {
+        item.set_shown(shown_at);
+    }
+    if let Some(snoozed_until) = snoozed_until {
+        item.snooze(snoozed_until);
+    }
+    item.set_category(category.as_deref().map(str::parse).transpose()?);
+    item.set_tags(tags.split_whitespace());
+    item.set_links(
+        links
+            .split_whitespace()
+            .map(Url::parse)
+            .collect::<Result<Vec<_>, _>>()?,
+    );
+    if let (Some(kind), Some(file_id)) = (attachment_kind, attachment_file_id) {
+        item.set_attachment(Some(Attachment {
+            kind: kind.parse()?,
+            file_id,
+        }));
+    }
+    if let (Some(chat_id), Some(message_id)) = (source_chat_id, source_message_id) {
+        item.set_source(Some(Source {
+            chat_id: ChatId(chat_id),
+            message_id: MessageId(message_id),
+        }));
+    }
+    item.set_forward_origin(forward_author.map(|author| ForwardOrigin {
+        author,
+        chat_id: forward_chat_id.map(ChatId),
+        message_id: forward_message_id.map(MessageId),
+    }));
+
+    Ok((Key(key), item))
+}
+
+/// Fetch single item, either from the pool or inside a transaction
+async fn fetch_item<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    list: &ListId,
+    key: &Key,
+) -> Result<Option<ContentItem>> {
+    let row = sqlx::query(&format!(
+        "SELECT {ITEM_COLUMNS} FROM items
+        WHERE list_id = ? AND key = ?",
+    ))
+    .bind(list.0)
+    .bind(key.as_ref())
+    .fetch_optional(executor)
+    .await
+    .wrap_err_with(|| format!("failed to get item from SQLite by key `{key:?}`"))?;
+
+    Ok(row.map(from_row).transpose()?.map(|(_, item)| item))
+}
+
+/// Insert or replace single item, either through the pool or inside a transaction
+async fn store_item<'e, E: SqliteExecutor<'e>>(
+    executor: E,
+    list: &ListId,
+    key: &Key,
+    value: &ContentItem,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO items (
+            list_id, key, author_id, author, content, added_at, read_at, archived_at, shown_at,
+            snoozed_until, category, tags, links, attachment_kind, attachment_file_id,
+            source_chat_id, source_message_id, forward_author, forward_chat_id, forward_message_id
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (list_id, key) DO UPDATE SET
+            author_id = excluded.author_id,
+            author = excluded.author,
+            content = excluded.content,
+            added_at = excluded.added_at,
+            read_at = excluded.read_at,
+            archived_at = excluded.archived_at,
+            shown_at = excluded.shown_at,
+            snoozed_until = excluded.snoozed_until,
+            category = excluded.category,
+            tags = excluded.tags,
+            links = excluded.links,
+            attachment_kind = excluded.attachment_kind,
+            attachment_file_id = excluded.attachment_file_id,
+            source_chat_id = excluded.source_chat_id,
+            source_message_id = excluded.source_message_id,
+            forward_author = excluded.forward_author,
+            forward_chat_id = excluded.forward_chat_id,
+            forward_message_id = excluded.forward_message_id",
+    )
+    .bind(list.0)
+    .bind(key.as_ref())
+    .bind(value.author_id().map(|author_id| author_id.0 as i64))
+    .bind(value.author())
+    .bind(value.content())
+    .bind(value.added_at())
+    .bind(value.read_at())
+    .bind(value.archived_at())
+    .bind(value.shown_at())
+    .bind(value.snoozed_until())
+    .bind(value.category().map(Category::as_str))
+    .bind(value.tags().join(" "))
+    .bind(
+        value
+            .links()
+            .iter()
+            .map(Url::as_str)
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+    .bind(
+        value
+            .attachment()
+            .map(|attachment| attachment.kind.as_str()),
+    )
+    .bind(
+        value
+            .attachment()
+            .map(|attachment| attachment.file_id.as_str()),
+    )
+    .bind(value.source().map(|source| source.chat_id.0))
+    .bind(value.source().map(|source| source.message_id.0))
+    .bind(value.forward_origin().map(|origin| origin.author.as_str()))
+    .bind(
+        value
+            .forward_origin()
+            .and_then(|origin| origin.chat_id)
+            .map(|chat_id| chat_id.0),
+    )
+    .bind(
+        value
+            .forward_origin()
+            .and_then(|origin| origin.message_id)
+            .map(|message_id| message_id.0),
+    )
+    .execute(executor)
+    .await
+    .wrap_err("failed to set item via SQLite")?;
+
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl StorageBackend for SqliteStorage {
+    #[tracing::instrument(err, skip(self))]
+    async fn get(&self, list: &ListId, key: &Key) -> Result<Option<ContentItem>> {
+        fetch_item(&self.pool, list, key).await
+    }
+
+    #[tracing::instrument(err, skip(self, value))]
+    async fn set(&mut self, list: &ListId, key: &Key, value: ContentItem) -> Result<()> {
+        store_item(&self.pool, list, key, &value).await
+    }
+
+    #[tracing::instrument(err, skip(self, f))]
+    async fn update<F>(&mut self, list: &ListId, key: &Key, mut f: F) -> Result<Option<ContentItem>>
+    where
+        F: FnMut(&mut ContentItem) + Send,
+    {
+        // dropped transaction is rolled back, e.g. on errors or when the future is cancelled
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .wrap_err("failed to begin SQLite transaction")?;
+
+        // a write takes the write lock before the read,
+        // so nobody can change the item between our read and write
+        sqlx::query("UPDATE items SET key = key WHERE list_id = ? AND key = ?")
+            .bind(list.0)
+            .bind(key.as_ref())
+            .execute(&mut *transaction)
+            .await
+            .wrap_err("failed to lock SQLite database for update")?;
+
+        let Some(mut item) = fetch_item(&mut *transaction, list, key).await? else {
+            return Ok(None);
+        };
+
+        f(&mut item);
+        store_item(&mut *transaction, list, key, &item).await?;
+
+        transaction
+            .commit()
+            .await
+            .wrap_err("failed to commit SQLite transaction")?;
+
+        Ok(Some(item))
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_all(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = ? AND read_at IS NULL AND archived_at IS NULL",
+        ))
+        .bind(list.0)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get items from SQLite")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn dump(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = ?",
+        ))
+        .bind(list.0)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to dump items from SQLite")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_lists(&self) -> Result<Vec<ListId>> {
+        let lists: Vec<i64> =
+            sqlx::query_scalar("SELECT list_id FROM items UNION SELECT list_id FROM chat_settings")
+                .fetch_all(&self.pool)
+                .await
+                .wrap_err("failed to get lists from SQLite")?;
+
+        Ok(lists.into_iter().map(ListId).collect())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_archived(&self, list: &ListId) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = ? AND archived_at IS NOT NULL",
+        ))
+        .bind(list.0)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get archived items from SQLite")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_history(
+        &self,
+        list: &ListId,
+        filter: &HistoryFilter,
+    ) -> Result<Vec<(Key, ContentItem)>> {
+        let (author_id, username) = match &filter.author {
+            Some((user_id, username)) => (Some(user_id.0 as i64), Some(username.as_str())),
+            None => (None, None),
+        };
+
+        // timestamps are stored as text, possibly with different offsets,
+        // so they're compared as julian days
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = ?1 AND read_at IS NOT NULL AND archived_at IS NULL
+                AND (?2 IS NULL OR julianday(read_at) >= julianday(?2))
+                AND (?3 IS NULL OR julianday(read_at) < julianday(?3))
+                AND (?4 IS NULL OR author_id = ?4 OR (author_id IS NULL AND author = ?5))
+            ORDER BY julianday(read_at), key",
+        ))
+        .bind(list.0)
+        .bind(filter.from)
+        .bind(filter.to)
+        .bind(author_id)
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get history from SQLite")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_user_items(
+        &self,
+        list: &ListId,
+        user_id: UserId,
+        username: &str,
+    ) -> Result<HashMap<Key, ContentItem>> {
+        sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = ? AND read_at IS NULL AND archived_at IS NULL
+                AND (author_id = ? OR (author_id IS NULL AND author = ?))",
+        ))
+        .bind(list.0)
+        .bind(user_id.0 as i64)
+        .bind(username)
+        .fetch_all(&self.pool)
+        .await
+        .wrap_err("failed to get user items from SQLite")?
+        .into_iter()
+        .map(from_row)
+        .collect()
+    }
+
+    async fn get_now(&self) -> Result<OffsetDateTime> {
+        Ok(OffsetDateTime::now_utc())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn delete(&mut self, list: &ListId, key: &Key) -> Result<()> {
+        sqlx::query("DELETE FROM items WHERE list_id = ? AND key = ?")
+            .bind(list.0)
+            .bind(key.as_ref())
+            .execute(&self.pool)
+            .await
+            .wrap_err_with(|| format!("failed to delete item by key {key:?}"))?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(fields(random_key), err, skip(self))]
+    async fn get_random(
+        &self,
+        list: &ListId,
+        options: &RandomOptions,
+    ) -> Result<Option<(Key, ContentItem)>> {
+        if !options.is_uniform() {
+            return random::get_random(self, list, options).await;
+        }
+
+        let now = self.get_now().await?;
+        let row = sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM items
+            WHERE list_id = ? AND read_at IS NULL AND archived_at IS NULL
+                AND (snoozed_until IS NULL OR julianday(snoozed_until) <= julianday(?))
+            ORDER BY RANDOM() LIMIT 1",
+        ))
+        .bind(list.0)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .wrap_err("failed to get random item from SQLite")?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let (key, item) = from_row(row)?;
+        tracing::Span::current().record("random_key", &key.0);
+
+        Ok(Some((key, item)))
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn get_settings(&self, list: &ListId) -> Result<ChatSettings> {
+        let settings: Option<String> =
+            sqlx::query_scalar("SELECT settings FROM chat_settings WHERE list_id = ?")
+                .bind(list.0)
+                .fetch_optional(&self.pool)
+                .await
+                .wrap_err("failed to get chat settings from SQLite")?;
+
+        settings
+            .map(|settings| {
+                serde_json::from_str(&settings).wrap_err("failed to deserialize chat settings")
+            })
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn set_settings(&mut self, list: &ListId, settings: &ChatSettings) -> Result<()> {
+        let settings =
+            serde_json::to_string(settings).wrap_err("failed to serialize chat settings")?;
+
+        sqlx::query(
+            "INSERT INTO chat_settings (list_id, settings) VALUES (?, ?)
+            ON CONFLICT (list_id) DO UPDATE SET settings = excluded.settings",
+        )
+        .bind(list.0)
+        .bind(settings)
+        .execute(&self.pool)
+        .await
+        .wrap_err("failed to set chat settings via SQLite")?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(err, skip(self))]
+    async fn health_check(&self) -> Result<()> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .wrap_err("failed to ping SQLite")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::conformance::conformance_tests;
+
+    async fn storage() -> SqliteStorage {
+        // every connection to `:memory:` gets its own database, so keep only one
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+
+        SqliteStorage::from_pool(pool).await.unwrap()
+    }
+
+    conformance_tests!(storage().await);
+}
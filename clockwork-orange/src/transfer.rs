@@ -0,0 +1,222 @@
+//! Copying items between storage backends, e.g. when moving off Redis.
+//!
+//! Lists are copied one by one with their keys intact, so buttons in the chat
+//! history keep working after the move, along with settings of their chats.
+//! Every copied list is read back from the target and compared with the source.
+
+use color_eyre::{eyre::WrapErr, Result};
+
+use crate::{
+    content_item::ContentItem,
+    storage::{ListId, StorageBackend},
+};
+
+/// Outcome of copying a single list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListReport {
+    pub list: ListId,
+    /// Items in the source list, read ones included
+    pub items: usize,
+    /// Read items among them
+    pub read: usize,
+    /// Source items which were present in the target before copying
+    pub existing: usize,
+    /// Items written to the target, zero on dry run
+    pub copied: usize,
+    /// Source items found intact in the target after copying, zero on dry run
+    pub verified: usize,
+}
+
+impl ListReport {
+    /// Whether every source item has made it to the target
+    pub fn is_complete(&self) -> bool {
+        self.verified == self.items
+    }
+}
+
+/// Copy all items of the list from `source` to `target`, overwriting items with the same keys.
+///
+/// With `dry_run` nothing is written, only the counts are collected.
+#[tracing::instrument(err, skip(source, target))]
+pub async fn transfer_list<S, T>(
+    source: &S,
+    target: &mut T,
+    list: &ListId,
+    dry_run: bool,
+) -> Result<ListReport>
+where
+    S: StorageBackend,
+    T: StorageBackend,
+{
+    let items = source
+        .dump(list)
+        .await
+        .wrap_err_with(|| format!("failed to read list {list} from the source"))?;
+    let existing = target
+        .dump(list)
+        .await
+        .wrap_err_with(|| format!("failed to read list {list} from the target"))?;
+
+    let mut report = ListReport {
+        list: *list,
+        items: items.len(),
+        read: items.values().filter(|item| item.is_read()).count(),
+        existing: items
+            .keys()
+            .filter(|key| existing.contains_key(key))
+            .count(),
+        copied: 0,
+        verified: 0,
+    };
+    if dry_run {
+        return Ok(report);
+    }
+
+    for (key, item) in &items {
+        target
+            .set(list, key, item.clone())
+            .await
+            .wrap_err_with(|| format!("failed to copy item {key:?} of list {list}"))?;
+        report.copied += 1;
+    }
+
+    let settings = source
+        .get_settings(list)
+        .await
+        .wrap_err_with(|| format!("failed to read settings of list {list} from the source"))?;
+    target
+        .set_settings(list, &settings)
+        .await
+        .wrap_err_with(|| format!("failed to copy settings of list {list}"))?;
+
+    let copied = target
+        .dump(list)
+        .await
+        .wrap_err_with(|| format!("failed to read list {list} back from the target"))?;
+    report.verified = items
+        .iter()
+        .filter(|(key, item)| {
+            copied
+                .get(*key)
+                .is_some_and(|copy| is_same_item(item, copy))
+        })
+        .count();
+
+    Ok(report)
+}
+
+/// Compare items, ignoring sub-second precision of timestamps –
+/// backends store them with different precision
+fn is_same_item(a: &ContentItem, b: &ContentItem) -> bool {
+    let seconds = |time: Option<time::OffsetDateTime>| time.map(|time| time.unix_timestamp());
+
+    a.author_id() == b.author_id()
+        && a.author() == b.author()
+        && a.content() == b.content()
+        && seconds(a.added_at()) == seconds(b.added_at())
+        && seconds(a.read_at()) == seconds(b.read_at())
+        && seconds(a.archived_at()) == seconds(b.archived_at())
+        && seconds(a.shown_at()) == seconds(b.shown_at())
+        && seconds(a.snoozed_until()) == seconds(b.snoozed_until())
+        && a.category() == b.category()
+        && a.tags() == b.tags()
+        && a.links() == b.links()
+        && a.attachment() == b.attachment()
+        && a.source() == b.source()
+        && a.forward_origin() == b.forward_origin()
+}
+
+#[cfg(test)]
+mod tests {
+    use teloxide::types::{ChatId, UserId};
+    use time::OffsetDateTime;
+
+    use crate::{
+        settings::ChatSettings,
+        storage::{Key, MemoryStorage, Weighting},
+    };
+
+    use super::*;
+
+    /// Storage with one unread and one read item in the list, and custom settings
+    async fn source(list: &ListId) -> MemoryStorage {
+        let mut storage = MemoryStorage::new();
+
+        let item = ContentItem::new(
+            UserId(1),
+            "alice",
+            "https://example.com",
+            OffsetDateTime::UNIX_EPOCH,
+        );
+        storage.set(list, &Key::generate(), item).await.unwrap();
+
+        let mut read = ContentItem::legacy("bob", "https://example.com/read");
+        read.set_read(OffsetDateTime::UNIX_EPOCH);
+        storage.set(list, &Key::generate(), read).await.unwrap();
+
+        let mut settings = ChatSettings::default();
+        settings.random.weighting = Weighting::Older;
+        storage.set_settings(list, &settings).await.unwrap();
+
+        storage
+    }
+
+    #[tokio::test]
+    /// Test that all items are copied and verified
+    async fn test_transfer() {
+        let list = ChatId(1).into();
+        let source = source(&list).await;
+        let mut target = MemoryStorage::new();
+
+        let report = transfer_list(&source, &mut target, &list, false)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            report,
+            ListReport {
+                list,
+                items: 2,
+                read: 1,
+                existing: 0,
+                copied: 2,
+                verified: 2,
+            }
+        );
+        assert!(report.is_complete());
+        assert_eq!(
+            target.dump(&list).await.unwrap(),
+            source.dump(&list).await.unwrap()
+        );
+        assert_eq!(
+            target.get_settings(&list).await.unwrap(),
+            source.get_settings(&list).await.unwrap()
+        );
+
+        // re-running overwrites the same keys
+        let report = transfer_list(&source, &mut target, &list, false)
+            .await
+            .unwrap();
+        assert_eq!(report.existing, 2);
+        assert_eq!(target.dump(&list).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    /// Test that dry run only counts items
+    async fn test_dry_run() {
+        let list = ChatId(1).into();
+        let source = source(&list).await;
+        let mut target = MemoryStorage::new();
+
+        let report = transfer_list(&source, &mut target, &list, true)
+            .await
+            .unwrap();
+
+        assert_eq!((report.items, report.copied, report.verified), (2, 0, 0));
+        assert!(target.dump(&list).await.unwrap().is_empty());
+        assert_eq!(
+            target.get_settings(&list).await.unwrap(),
+            ChatSettings::default()
+        );
+    }
+}